@@ -1,27 +1,105 @@
-use midgar::{self, KeyCode, Midgar};
+use std::env;
+use std::path::PathBuf;
 
+use cgmath::MetricSpace;
+use midgar::{self, Midgar};
+use rand::Rng;
+use sdl2::video::FullscreenType;
+
+use crate::bindings::{BindingsMenu, KeyBindings};
 use crate::config;
 use cgmath;
-use crate::entities::Camera;
-use crate::world::GameWorld;
-use crate::renderer::GameRenderer;
-use crate::sounds::{Sounds, AudioController};
+use crate::entities::{Camera, CatArchetype};
+use crate::input::{Gamepads, InputSource, InputState, KeyboardSource};
+use crate::level::{self, LevelDef};
+use crate::world::{AppRequest, GameState, GameWorld, SoundEvent};
+use crate::renderer::{GameRenderer, Menu};
+use crate::replay::{Replay, ReplayPlayer};
+use crate::save::SaveData;
+use crate::settings::{SettingsEvent, SettingsMenu};
+use crate::sounds::{self, AudioBackend, DOG_WOOF, DOG_YIP};
+
+// SDL_WINDOW_INPUT_FOCUS, set in the window's flags while it has keyboard focus.
+const WINDOW_INPUT_FOCUS: u32 = 0x200;
+
+/// Command line options. All of them are optional:
+///
+/// * `--seed <n>` starts the world from a fixed RNG seed.
+/// * `--record <path>` records the session and writes a replay to `path` on exit.
+/// * `--replay <path>` plays back a recorded replay, then hands control back to the player.
+#[derive(Default)]
+struct Options {
+    seed: Option<u64>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.seed = args.next().and_then(|s| s.parse().ok()),
+                "--record" => options.record = args.next().map(PathBuf::from),
+                "--replay" => options.replay = args.next().map(PathBuf::from),
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        options
+    }
+}
 
 pub struct GameApp<'a> {
     camera: Camera,
     world: GameWorld,
-    sounds: Sounds,
+    sounds: Box<dyn AudioBackend>,
     renderer: GameRenderer<'a>,
+    gamepads: Gamepads,
+    bindings: KeyBindings,
+    bindings_menu: Option<BindingsMenu>,
+    settings_menu: Option<SettingsMenu>,
+    save: SaveData,
+    had_focus: bool,
+    fullscreen: bool,
+
+    recording: Option<(Replay, PathBuf)>,
+    playback: Option<ReplayPlayer>,
 }
 
 impl<'a> midgar::App for GameApp<'a> {
     fn create(midgar: &Midgar) -> Self {
-        let mut sounds = Sounds::new();
-        sounds.intro_music.set_volume(0.2);
-        sounds.intro_music.play();
+        let options = Options::from_args();
+        let save = SaveData::load();
+
+        let replay = options.replay.map(|path| Replay::load(path).expect("Error on loading replay."));
+        let (seed, start_level) = match replay {
+            Some(ref replay) => (replay.seed, replay.start_level),
+            None => (options.seed.unwrap_or_else(|| rand::thread_rng().gen()), save.unlocked_level),
+        };
+        let recording = options.record.map(|path| (Replay::new(seed, start_level), path));
+        let playback = replay.map(ReplayPlayer::new);
+
+        let levels = LevelDef::load_all("assets/levels.ron");
+        let arenas = LevelDef::load_all("assets/versus.ron");
+        let archetypes = CatArchetype::load_all("assets/cats.ron");
+        level::check_levels("assets/levels.ron", &levels, &archetypes).expect("Error on checking levels");
+        level::check_levels("assets/versus.ron", &arenas, &archetypes).expect("Error on checking versus arenas");
+
+        let mut sounds = sounds::new_audio();
+        for archetype in &archetypes {
+            sounds.preload(&archetype.sounds.meow);
+            for angry in &archetype.sounds.angry {
+                sounds.preload(angry);
+            }
+        }
+        sounds.set_master_volume(save.settings.master_volume);
+        sounds.set_music_volume(save.settings.music_volume);
+        sounds.set_sfx_volume(save.settings.sfx_volume);
+        sounds.start_music();
 
         GameApp {
-            world: GameWorld::new(),
+            world: GameWorld::new(seed, levels, arenas, archetypes, start_level),
             camera: Camera {
                 pos: cgmath::vec2(config::GAME_SIZE.x as f32 / 2.0, config::GAME_SIZE.y as f32 / 2.0),
                 bounds: config::GAME_SIZE.cast::<f32>(),
@@ -29,23 +107,178 @@ impl<'a> midgar::App for GameApp<'a> {
             },
             renderer: GameRenderer::new(midgar),
             sounds,
+            gamepads: Gamepads::new(),
+            bindings: KeyBindings::load(),
+            bindings_menu: None,
+            settings_menu: None,
+            had_focus: true,
+            // The window always opens windowed. step switches it to fullscreen if the settings say so.
+            fullscreen: false,
+            save,
+            recording,
+            playback,
         }
     }
 
     fn step(&mut self, midgar: &mut Midgar) {
-        if midgar.input().was_key_pressed(KeyCode::Escape) {
-            midgar.set_should_exit();
+        let dt = midgar.time().delta_time() as f32;
+        self.update_window(midgar);
+        // Keep the music going and gamepads read even while a menu has taken over.
+        self.sounds.update();
+        self.gamepads.update();
+
+        // The rebinding screen takes over the keyboard while it's open.
+        if let Some(ref mut menu) = self.bindings_menu {
+            if menu.update(midgar) {
+                self.bindings = menu.bindings.clone();
+                if let Err(e) = self.bindings.save() {
+                    eprintln!("{}", e);
+                }
+                self.bindings_menu = None;
+            }
+            self.render(midgar, dt);
+            return;
+        }
+        // So does the settings screen, applying changes as they're made.
+        let settings_event = self.settings_menu.as_mut().map(|menu| menu.update(midgar));
+        if let Some(event) = settings_event {
+            match event {
+                SettingsEvent::Changed => self.apply_settings(),
+                SettingsEvent::OpenKeys => self.bindings_menu = Some(BindingsMenu::new(self.bindings.clone())),
+                SettingsEvent::Done => {
+                    self.settings_menu = None;
+                    if let Err(e) = self.save.save() {
+                        eprintln!("{}", e);
+                    }
+                }
+                SettingsEvent::Nothing => {}
+            }
+            self.render(midgar, dt);
+            return;
+        }
+        if self.world.game_state == GameState::StartMenu && midgar.input().was_key_pressed(self.bindings.menu.settings) {
+            self.settings_menu = Some(SettingsMenu::new(self.save.settings.clone()));
             return;
         }
 
-        let dt = midgar.time().delta_time() as f32;
+        let keyboard = KeyboardSource::new(midgar, &self.bindings);
+        let sources: [&dyn InputSource; 2] = [&keyboard, &self.gamepads.source];
 
-        if !self.sounds.intro_music.is_playing() && !self.sounds.background_music.is_playing() {
-            self.sounds.background_music.set_volume(0.2);
-            self.sounds.background_music.play();
+        // Replays drive the world with their own timestep and game speed so they play out exactly
+        // as recorded. Recordings store the timestep after game speed is applied.
+        let game_speed = self.save.settings.game_speed;
+        let (mut input, world_dt, game_speed) = match self.playback.as_mut().map(|player| player.next_frame()) {
+            Some(Some(frame)) => (frame.input, frame.dt, frame.game_speed),
+            Some(None) => {
+                println!("Replay finished.");
+                self.playback = None;
+                (InputState::poll(&sources), dt * game_speed, game_speed)
+            }
+            None => (InputState::poll(&sources), dt * game_speed, game_speed),
+        };
+        // Pause when the window loses focus. This goes through the input so replays see it too.
+        let has_focus = midgar.graphics().display().window().window_flags() & WINDOW_INPUT_FOCUS != 0;
+        if self.had_focus && !has_focus && self.playback.is_none() && self.world.can_pause() {
+            input.pause = true;
+        }
+        self.had_focus = has_focus;
+        if let Some((ref mut replay, _)) = self.recording {
+            replay.record(world_dt, game_speed, input);
         }
-        self.world.update(midgar, dt);
+        let was_complete = self.world.result.is_some();
+        self.world.game_speed = game_speed;
+        self.world.update(&input, world_dt);
+        self.play_sounds();
+        self.camera.update(self.world.camera_focus(), &self.world.level.bounds, world_dt);
+        match self.world.app_request.take() {
+            Some(AppRequest::OpenSettings) => self.settings_menu = Some(SettingsMenu::new(self.save.settings.clone())),
+            Some(AppRequest::Quit) => midgar.set_should_exit(),
+            None => {}
+        }
+        // Remember finished levels, but don't let replays touch the player's progress.
+        if !was_complete && self.playback.is_none() {
+            if let Some(result) = self.world.result {
+                self.save.record_result(self.world.level.level_num, result, self.world.max_level());
+                if let Err(e) = self.save.save() {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+
+        self.render(midgar, dt);
+    }
+}
+
+impl<'a> GameApp<'a> {
+    /// Draws the game, or the open menu instead. The rebinding screen opens on top of the settings.
+    fn render(&mut self, midgar: &Midgar, dt: f32) {
+        let menu = match (&self.bindings_menu, &self.settings_menu) {
+            (Some(menu), _) => Some(Menu::Bindings(menu)),
+            (None, Some(menu)) => Some(Menu::Settings(menu)),
+            (None, None) => None,
+        };
+        self.renderer.render(midgar, dt, &self.world, &self.camera, &self.bindings, menu);
+    }
+
+    /// Takes on the settings screen's changes and applies the audio ones right away.
+    fn apply_settings(&mut self) {
+        if let Some(ref menu) = self.settings_menu {
+            self.save.settings = menu.settings.clone();
+        }
+        let settings = &self.save.settings;
+        self.sounds.set_master_volume(settings.master_volume);
+        self.sounds.set_music_volume(settings.music_volume);
+        self.sounds.set_sfx_volume(settings.sfx_volume);
+    }
 
-        self.renderer.render(midgar, dt, &self.world, &self.camera);
+    /// Plays the sounds the world asked for this tick, panned by where they are on screen.
+    fn play_sounds(&mut self) {
+        let half_view = self.camera.view_size().x / 2.0;
+        for event in &self.world.sound_events {
+            let (clip, pos) = match *event {
+                SoundEvent::Woof(dog) => match self.world.dogs.get(dog) {
+                    Some(dog) => (DOG_WOOF, dog.pos),
+                    None => continue,
+                },
+                SoundEvent::Yip(dog) => match self.world.dogs.get(dog) {
+                    Some(dog) => (DOG_YIP, dog.pos),
+                    None => continue,
+                },
+                SoundEvent::Meow { cat, angry } => match self.world.cats.get(cat) {
+                    Some(cat) if angry => (cat.angry_meow_clip.as_str(), cat.pos),
+                    Some(cat) => (cat.meow_clip.as_str(), cat.pos),
+                    None => continue,
+                },
+            };
+            let pan = (pos.x - self.camera.pos.x) / half_view;
+            let distance = self.world.dogs.iter()
+                .map(|dog| dog.pos.distance(pos))
+                .fold(f32::INFINITY, f32::min);
+            self.sounds.play(clip, pan, distance);
+        }
+    }
+
+    /// Switches between fullscreen and windowed when the settings ask for it.
+    fn update_window(&mut self, midgar: &mut Midgar) {
+        if self.fullscreen == self.save.settings.fullscreen {
+            return;
+        }
+        self.fullscreen = self.save.settings.fullscreen;
+        let fullscreen_type = if self.fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        let window = midgar.graphics_mut().display_mut().window_mut();
+        if let Err(e) = window.set_fullscreen(fullscreen_type) {
+            eprintln!("Error on changing window mode: {}", e);
+        }
+    }
+}
+
+impl<'a> Drop for GameApp<'a> {
+    fn drop(&mut self) {
+        if let Some((ref replay, ref path)) = self.recording {
+            match replay.save(path) {
+                Ok(()) => println!("Saved replay to {}", path.display()),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
     }
 }
@@ -0,0 +1,365 @@
+use std::fs;
+use std::path::PathBuf;
+
+use midgar::{KeyCode, Midgar};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::input::{Action, MAX_PLAYERS};
+
+/// Keys are written to the bindings file by name, e.g. "Left Shift".
+mod key_name {
+    use midgar::KeyCode;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &KeyCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&key.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KeyCode::from_name(&name).ok_or_else(|| D::Error::custom(format!("unknown key {:?}", name)))
+    }
+
+    /// For keys that might be missing, together with `#[serde(default)]`.
+    pub fn deserialize_some<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<KeyCode>, D::Error> {
+        deserialize(deserializer).map(Some)
+    }
+}
+
+/// The keys one player drives their dog with.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DogKeys {
+    #[serde(with = "key_name")]
+    pub left: KeyCode,
+    #[serde(with = "key_name")]
+    pub right: KeyCode,
+    #[serde(with = "key_name")]
+    pub up: KeyCode,
+    #[serde(with = "key_name")]
+    pub down: KeyCode,
+    #[serde(with = "key_name")]
+    pub woof: KeyCode,
+    #[serde(with = "key_name")]
+    pub sprint: KeyCode,
+}
+
+/// Menu keys, shared by both players.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MenuKeys {
+    #[serde(with = "key_name")]
+    pub confirm: KeyCode,
+    #[serde(with = "key_name")]
+    pub back: KeyCode,
+    #[serde(with = "key_name")]
+    pub restart: KeyCode,
+    #[serde(with = "key_name")]
+    pub next_level: KeyCode,
+    #[serde(with = "key_name", default = "default_skip_level_key")]
+    pub skip_level: KeyCode,
+    #[serde(with = "key_name")]
+    pub versus: KeyCode,
+    /// Opens the settings screen from the title screen.
+    #[serde(with = "key_name", default = "default_settings_key")]
+    pub settings: KeyCode,
+}
+
+fn default_skip_level_key() -> KeyCode {
+    KeyCode::K
+}
+
+fn default_settings_key() -> KeyCode {
+    KeyCode::O
+}
+
+impl MenuKeys {
+    pub fn key_for(&self, action: Action) -> Option<KeyCode> {
+        match action {
+            Action::Confirm => Some(self.confirm),
+            Action::Back => Some(self.back),
+            Action::Restart => Some(self.restart),
+            Action::NextLevel => Some(self.next_level),
+            Action::SkipLevel => Some(self.skip_level),
+            Action::Versus => Some(self.versus),
+            _ => None,
+        }
+    }
+}
+
+const DOG_KEY_NAMES: &[&str] = &["Left", "Right", "Up", "Down", "Woof", "Sprint"];
+const MENU_KEY_NAMES: &[&str] = &["Confirm", "Back", "Restart", "Next level", "Skip level", "Versus", "Settings"];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyBindings {
+    #[serde(deserialize_with = "deserialize_players")]
+    pub players: [DogKeys; MAX_PLAYERS],
+    pub menu: MenuKeys,
+}
+
+/// `DogKeys` as read from the bindings file. Files saved before sprinting don't have a sprint key.
+#[derive(Deserialize)]
+struct SavedDogKeys {
+    #[serde(with = "key_name")]
+    left: KeyCode,
+    #[serde(with = "key_name")]
+    right: KeyCode,
+    #[serde(with = "key_name")]
+    up: KeyCode,
+    #[serde(with = "key_name")]
+    down: KeyCode,
+    #[serde(with = "key_name")]
+    woof: KeyCode,
+    #[serde(default, deserialize_with = "key_name::deserialize_some")]
+    sprint: Option<KeyCode>,
+}
+
+/// Reads each player's keys, filling in their own default for any key the file doesn't have.
+fn deserialize_players<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[DogKeys; MAX_PLAYERS], D::Error> {
+    let saved = <[SavedDogKeys; MAX_PLAYERS]>::deserialize(deserializer)?;
+    let mut players = KeyBindings::default().players;
+    for (keys, saved) in players.iter_mut().zip(saved.iter()) {
+        keys.left = saved.left;
+        keys.right = saved.right;
+        keys.up = saved.up;
+        keys.down = saved.down;
+        keys.woof = saved.woof;
+        if let Some(sprint) = saved.sprint {
+            keys.sprint = sprint;
+        }
+    }
+    Ok(players)
+}
+
+impl Default for KeyBindings {
+    /// Both players share the keyboard: player 1 on the arrows, player 2 on WASD.
+    fn default() -> Self {
+        KeyBindings {
+            players: [
+                DogKeys {
+                    left: KeyCode::Left,
+                    right: KeyCode::Right,
+                    up: KeyCode::Up,
+                    down: KeyCode::Down,
+                    woof: KeyCode::Space,
+                    sprint: KeyCode::RShift,
+                },
+                DogKeys {
+                    left: KeyCode::A,
+                    right: KeyCode::D,
+                    up: KeyCode::W,
+                    down: KeyCode::S,
+                    woof: KeyCode::LShift,
+                    sprint: KeyCode::LCtrl,
+                },
+            ],
+            menu: MenuKeys {
+                confirm: KeyCode::Return,
+                back: KeyCode::Tab,
+                restart: KeyCode::R,
+                next_level: KeyCode::N,
+                skip_level: default_skip_level_key(),
+                versus: KeyCode::V,
+                settings: default_settings_key(),
+            },
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Where the bindings file lives, under the user's config directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("cat-chaser").join("bindings.ron"))
+    }
+
+    /// Loads the bindings file, falling back to the default keys if there isn't a usable one.
+    pub fn load() -> Self {
+        let path = match KeyBindings::path() {
+            Some(path) => path,
+            None => return KeyBindings::default(),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return KeyBindings::default(),
+        };
+        ron::de::from_str(&text).unwrap_or_else(|e| {
+            eprintln!("Error on parsing key bindings {}: {}. Using the default keys.", path.display(), e);
+            KeyBindings::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = KeyBindings::path().ok_or_else(|| "No config directory to save key bindings to".to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Error on creating config directory {}: {}", dir.display(), e))?;
+        }
+        let text = ron::ser::to_string(self)
+            .map_err(|e| format!("Error on serializing key bindings: {}", e))?;
+        fs::write(&path, text)
+            .map_err(|e| format!("Error on writing key bindings {}: {}", path.display(), e))
+    }
+
+    /// How many keys the rebinding screen lists.
+    pub fn slot_count(&self) -> usize {
+        MAX_PLAYERS * DOG_KEY_NAMES.len() + MENU_KEY_NAMES.len()
+    }
+
+    pub fn slot_label(&self, slot: usize) -> String {
+        let dog_slots = MAX_PLAYERS * DOG_KEY_NAMES.len();
+        if slot < dog_slots {
+            format!("P{} {}", slot / DOG_KEY_NAMES.len() + 1, DOG_KEY_NAMES[slot % DOG_KEY_NAMES.len()])
+        } else {
+            MENU_KEY_NAMES[slot - dog_slots].to_string()
+        }
+    }
+
+    pub fn slot_key(&self, slot: usize) -> KeyCode {
+        let dog_slots = MAX_PLAYERS * DOG_KEY_NAMES.len();
+        if slot < dog_slots {
+            let keys = &self.players[slot / DOG_KEY_NAMES.len()];
+            [keys.left, keys.right, keys.up, keys.down, keys.woof, keys.sprint][slot % DOG_KEY_NAMES.len()]
+        } else {
+            let menu = &self.menu;
+            [menu.confirm, menu.back, menu.restart, menu.next_level, menu.skip_level, menu.versus, menu.settings][slot - dog_slots]
+        }
+    }
+
+    /// Every key, in the order the rebinding screen lists them.
+    fn slots_mut(&mut self) -> Vec<&mut KeyCode> {
+        let mut slots = Vec::new();
+        for keys in &mut self.players {
+            slots.push(&mut keys.left);
+            slots.push(&mut keys.right);
+            slots.push(&mut keys.up);
+            slots.push(&mut keys.down);
+            slots.push(&mut keys.woof);
+            slots.push(&mut keys.sprint);
+        }
+        let menu = &mut self.menu;
+        slots.push(&mut menu.confirm);
+        slots.push(&mut menu.back);
+        slots.push(&mut menu.restart);
+        slots.push(&mut menu.next_level);
+        slots.push(&mut menu.skip_level);
+        slots.push(&mut menu.versus);
+        slots.push(&mut menu.settings);
+        slots
+    }
+
+    /// Binds `key` to a slot. Whatever had `key` before gets the slot's old key, so no key is
+    /// ever bound twice.
+    pub fn rebind(&mut self, slot: usize, key: KeyCode) {
+        let mut slots = self.slots_mut();
+        let old_key = *slots[slot];
+        for other in slots.iter_mut() {
+            if **other == key {
+                **other = old_key;
+            }
+        }
+        *slots[slot] = key;
+    }
+}
+
+/// Keys that can be bound. Layouts map these by the character they type, so AZERTY and Dvorak
+/// players get the letters they expect.
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Num0, KeyCode::Num1, KeyCode::Num2, KeyCode::Num3, KeyCode::Num4,
+    KeyCode::Num5, KeyCode::Num6, KeyCode::Num7, KeyCode::Num8, KeyCode::Num9,
+    KeyCode::Kp0, KeyCode::Kp1, KeyCode::Kp2, KeyCode::Kp3, KeyCode::Kp4,
+    KeyCode::Kp5, KeyCode::Kp6, KeyCode::Kp7, KeyCode::Kp8, KeyCode::Kp9, KeyCode::KpEnter,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+    KeyCode::Space, KeyCode::Return, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LCtrl, KeyCode::RCtrl, KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Quote,
+    KeyCode::LeftBracket, KeyCode::RightBracket, KeyCode::Minus, KeyCode::Equals,
+    KeyCode::Backslash, KeyCode::Backquote,
+];
+
+/// The rebinding screen. It always navigates with the arrows, Enter and Escape, so nobody can
+/// lock themselves out of it.
+pub struct BindingsMenu {
+    pub bindings: KeyBindings,
+    pub selected: usize,
+    /// Whether the next key pressed gets bound to the selected slot.
+    pub waiting: bool,
+}
+
+impl BindingsMenu {
+    pub fn new(bindings: KeyBindings) -> Self {
+        BindingsMenu {
+            bindings,
+            selected: 0,
+            waiting: false,
+        }
+    }
+
+    /// Returns whether the menu is done.
+    pub fn update(&mut self, midgar: &Midgar) -> bool {
+        let input = midgar.input();
+
+        if self.waiting {
+            if input.was_key_pressed(KeyCode::Escape) {
+                self.waiting = false;
+            } else if let Some(&key) = BINDABLE_KEYS.iter().find(|&&key| input.was_key_pressed(key)) {
+                self.bindings.rebind(self.selected, key);
+                self.waiting = false;
+            }
+            return false;
+        }
+
+        let slot_count = self.bindings.slot_count();
+        if input.was_key_pressed(KeyCode::Escape) {
+            return true;
+        } else if input.was_key_pressed(KeyCode::Return) {
+            self.waiting = true;
+        } else if input.was_key_pressed(KeyCode::Backspace) {
+            self.bindings = KeyBindings::default();
+        } else if input.was_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + slot_count - 1) % slot_count;
+        } else if input.was_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % slot_count;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_bindings_files_get_the_new_keys() {
+        // A file from before sprinting, skipping levels and the settings key could be bound.
+        let dog_keys = |left: KeyCode, woof: KeyCode| {
+            format!("(left: {:?}, right: {:?}, up: {:?}, down: {:?}, woof: {:?})",
+                    left.name(), KeyCode::D.name(), KeyCode::W.name(), KeyCode::S.name(), woof.name())
+        };
+        let text = format!(
+            "(players: ({}, {}), menu: (confirm: {:?}, back: {:?}, restart: {:?}, next_level: {:?}, versus: {:?}))",
+            dog_keys(KeyCode::J, KeyCode::Space), dog_keys(KeyCode::A, KeyCode::LShift),
+            KeyCode::Return.name(), KeyCode::Tab.name(), KeyCode::R.name(), KeyCode::N.name(), KeyCode::V.name());
+        let bindings: KeyBindings = ron::de::from_str(&text).unwrap();
+        let defaults = KeyBindings::default();
+
+        assert!(bindings.players[0].left == KeyCode::J);
+        for player in 0..MAX_PLAYERS {
+            assert!(bindings.players[player].sprint == defaults.players[player].sprint);
+        }
+        assert!(bindings.menu.skip_level == defaults.menu.skip_level);
+        assert!(bindings.menu.settings == defaults.menu.settings);
+    }
+
+    #[test]
+    fn bindings_roundtrip() {
+        let mut bindings = KeyBindings::default();
+        bindings.rebind(5, KeyCode::P);
+        let text = ron::ser::to_string(&bindings).unwrap();
+        let loaded: KeyBindings = ron::de::from_str(&text).unwrap();
+        assert!(loaded.players[0].sprint == KeyCode::P);
+        assert!(loaded.players[1].sprint == KeyCode::LCtrl);
+    }
+}
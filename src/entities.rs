@@ -1,8 +1,14 @@
-use cgmath::{self, Vector2, InnerSpace};
-use midgar::KeyCode;
+use std::fs;
+
+use std::f32::consts::PI;
+
+use cgmath::{self, Vector2, InnerSpace, MetricSpace, Zero};
+use rand::Rng;
 use rand::distributions::{Distribution, Uniform};
 use rand::seq::SliceRandom;
-use crate::sounds::{Sound, Sounds, AudioController};
+use serde::Deserialize;
+
+use crate::world::SoundEvent;
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Facing {
@@ -17,24 +23,46 @@ const CANNONBALL_TIME: f32 = 1.25;
 const JITTER_AMOUNT: f32 = 2.0;
 const HIT_TIME: f32 = 0.5;
 const BLINK_FRAMES: u32 = 2;
-
-const BASIC_CAT_ANNOYANCE_RATE: f32 = 1.0;
-const BASIC_CAT_CALMING_RATE: f32 = 0.75;
-const BASIC_CAT_SPEED: f32 = 150.0;
-const BASIC_CAT_RW_RADIUS: f32 = 9.0;
-const BASIC_CAT_FLEE_SCALAR: f32 = 1.0;
-
-const KITTEN_ANNOYANCE_RATE: f32 = 0.0;
-const KITTEN_CALMING_RATE: f32 = 0.0;
-const KITTEN_SPEED: f32 = 175.0;
-const KITTEN_RW_RADIUS: f32 = 12.0;
-const KITTEN_FLEE_SCALAR: f32 = 1.5;
-
-const FAT_CAT_ANNOYANCE_RATE: f32 = 1.5;
-const FAT_CAT_CALMING_RATE: f32 = 0.5;
-const FAT_CAT_SPEED: f32 = 100.0;
-const FAT_CAT_RW_RADIUS: f32 = 6.0;
-const FAT_CAT_FLEE_SCALAR: f32 = 1.0;
+// Cats and the dog collide with obstacles as circles of this radius.
+const BODY_RADIUS: f32 = 10.0;
+// Most times to push a body out of obstacles in one move. Bodies wedged between furniture and a
+// wall settle for the last spot they were pushed to.
+const MAX_PUSH_OUT_PASSES: u32 = 4;
+// How quickly the camera catches up to what it's following. Higher is snappier.
+const CAMERA_FOLLOW_RATE: f32 = 5.0;
+// How far a bark reaches.
+pub const WOOF_RADIUS: f32 = 120.0;
+// Seconds between barks.
+const WOOF_COOLDOWN: f32 = 1.5;
+// How long the bark's wave takes to spread out to WOOF_RADIUS, in seconds.
+const WOOF_WAVE_TIME: f32 = 0.3;
+// How fast a bark knocks a cat away, before its archetype's woof_response.
+const WOOF_IMPULSE: f32 = 300.0;
+// How much a bark annoys a cat, before its annoyance_rate.
+const WOOF_ANNOYANCE: f32 = 0.25;
+// How quickly a bark's knockback wears off. Higher stops sooner.
+const IMPULSE_DECAY: f32 = 6.0;
+// Multiplies the dog's speed while sprinting.
+pub const SPRINT_SCALAR: f32 = 1.6;
+// How much stamina sprinting uses up, and walking or standing gets back, per second.
+const STAMINA_DRAIN: f32 = 0.5;
+const STAMINA_REGEN: f32 = 0.25;
+// A dog that runs out of stamina can't sprint again until it gets this much back.
+const WINDED_STAMINA: f32 = 0.5;
+// Multiplies how fast cats get annoyed when a sprinting dog chases them.
+const SPRINT_ANNOYANCE_SCALAR: f32 = 2.0;
+// How many cannonballs a dog can take before the level is failed.
+pub const DOG_HEALTH: u32 = 3;
+// How fast a cannonball knocks the dog back.
+const KNOCKBACK_SPEED: f32 = 400.0;
+// Penned cats this annoyed might make a break for it.
+const ESCAPE_ANNOYANCE: f32 = 0.5;
+// How much a bark winds up a penned cat, before its annoyance_rate. Enough to make it restless.
+const PEN_WOOF_ANNOYANCE: f32 = 0.75;
+// Barks never wind a penned cat up past this, so it tries to get out rather than cannonballing.
+const PEN_MAX_ANNOYANCE: f32 = 0.9;
+// Longest a cat spends trying to get out of a pen before giving up, in seconds.
+const ESCAPE_TIME: f32 = 1.0;
 
 pub const CAT_COLORS: &[[f32; 3]] = &[
     [203.0 / 255.0, 219.0 / 255.0, 252.0 / 255.0], // The default purple blue
@@ -43,6 +71,22 @@ pub const CAT_COLORS: &[[f32; 3]] = &[
     [255.0 / 255.0, 193.0 / 255.0, 229.0 / 255.0], // Not quite but sort of pink.
 ];
 
+/// Moves a body at `pos` inside `min`..`max` and out of any obstacles. Pushing out of one obstacle
+/// can land in another or past the edge of the level, so this goes around until nothing moves it.
+fn settle(pos: Vector2<f32>, min: Vector2<f32>, max: Vector2<f32>, obstacles: &[Obstacle]) -> Vector2<f32> {
+    let clamp = |pos: Vector2<f32>| cgmath::vec2(pos.x.max(min.x).min(max.x), pos.y.max(min.y).min(max.y));
+    let mut pos = clamp(pos);
+    for _ in 0..MAX_PUSH_OUT_PASSES {
+        let pushed = clamp(obstacles.iter()
+            .fold(pos, |pos, obstacle| obstacle.push_out_within(pos, BODY_RADIUS, min, max)));
+        if pushed == pos {
+            break;
+        }
+        pos = pushed;
+    }
+    pos
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum DogState {
     Chasing,
@@ -55,27 +99,131 @@ pub struct Dog {
     pub size: Vector2<f32>,
     pub facing: Facing,
 
-    pub left_key: KeyCode, // TODO: consider breaking this out into control struct
-    pub right_key: KeyCode,
-    pub up_key: KeyCode,
-    pub down_key: KeyCode,
+    pub player: usize, // index into the input's players, and PLAYER_CONTROLS
 
     pub dog_state: DogState,
     pub hit_time: f32,
     pub hit_frame: u32,
-
-    pub yip_sound: Sound,
-    pub woof_sound: Sound,
+    /// Seconds until the dog can bark again.
+    pub woof_cooldown: f32,
+    /// Seconds since the dog last barked.
+    pub woof_time: f32,
+    /// From 0.0 to 1.0. Sprinting uses it up.
+    pub stamina: f32,
+    pub sprinting: bool,
+    /// Whether the dog ran out of stamina and is catching its breath.
+    pub winded: bool,
+    pub health: u32,
+    pub knockback: Vector2<f32>,
 }
 
 impl Dog {
-    pub fn hit(&mut self) {
+    pub fn new(player: usize, pos: Vector2<f32>) -> Self {
+        Dog {
+            pos,
+            vel: Vector2::zero(),
+            size: cgmath::vec2(30.0, 30.0),
+            facing: Facing::Left,
+            player,
+            dog_state: DogState::Chasing,
+            hit_time: 0.0,
+            hit_frame: 0,
+            woof_cooldown: 0.0,
+            woof_time: WOOF_WAVE_TIME,
+            stamina: 1.0,
+            sprinting: false,
+            winded: false,
+            health: DOG_HEALTH,
+            knockback: Vector2::zero(),
+        }
+    }
+
+    /// Puts the dog back at `pos`, fresh and rested, for a new attempt at a level.
+    pub fn respawn(&mut self, pos: Vector2<f32>) {
+        self.pos = pos;
+        self.vel = Vector2::zero();
+        self.dog_state = DogState::Chasing;
+        self.woof_cooldown = 0.0;
+        self.stamina = 1.0;
+        self.winded = false;
+        self.health = DOG_HEALTH;
+        self.knockback = Vector2::zero();
+    }
+
+    /// Takes a cannonball flying along `dir`, which knocks the dog back that way.
+    pub fn hit(&mut self, dir: Vector2<f32>) {
         self.dog_state = DogState::Blinking(true);
         self.hit_time = HIT_TIME;
-        self.yip_sound.play();
+        self.health = self.health.saturating_sub(1);
+        self.knockback = dir * KNOCKBACK_SPEED;
+    }
+
+    /// Slides the dog along with any knockback, until it wears off.
+    pub fn apply_knockback(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], dt: f32) {
+        if self.knockback.is_zero() {
+            return;
+        }
+        let change = self.knockback * dt;
+        self.try_move(bounds, obstacles, change);
+        self.knockback *= (-IMPULSE_DECAY * dt).exp();
+        if self.knockback.magnitude2() < 1.0 {
+            self.knockback = Vector2::zero();
+        }
+    }
+
+    /// Barks if the dog has caught its breath. Returns whether it barked.
+    pub fn try_woof(&mut self) -> bool {
+        if self.woof_cooldown > 0.0 {
+            return false;
+        }
+        self.woof_cooldown = WOOF_COOLDOWN;
+        self.woof_time = 0.0;
+        true
+    }
+
+    /// How far the last bark's wave has spread, while it's still spreading.
+    pub fn woof_wave_radius(&self) -> Option<f32> {
+        if self.woof_time < WOOF_WAVE_TIME {
+            Some(WOOF_RADIUS * self.woof_time / WOOF_WAVE_TIME)
+        } else {
+            None
+        }
+    }
+
+    /// How ready the next bark is, from 0.0 just after barking to 1.0 when it can bark again.
+    pub fn woof_recharge(&self) -> f32 {
+        1.0 - self.woof_cooldown / WOOF_COOLDOWN
+    }
+
+    /// Sprints if the dog wants to and has the stamina for it, otherwise gets stamina back.
+    pub fn update_stamina(&mut self, wants_to_sprint: bool, dt: f32) {
+        self.sprinting = wants_to_sprint && !self.winded;
+        if self.sprinting {
+            self.stamina = (self.stamina - STAMINA_DRAIN * dt).max(0.0);
+            if self.stamina <= 0.0 {
+                self.winded = true;
+            }
+        } else {
+            self.stamina = (self.stamina + STAMINA_REGEN * dt).min(1.0);
+            if self.stamina >= WINDED_STAMINA {
+                self.winded = false;
+            }
+        }
+    }
+
+    /// Multiplies how fast cats fleeing this dog get annoyed.
+    pub fn annoyance_scalar(&self) -> f32 {
+        if self.sprinting {
+            SPRINT_ANNOYANCE_SCALAR
+        } else {
+            1.0
+        }
     }
 
     pub fn update(&mut self, dt: f32) {
+        self.woof_cooldown = (self.woof_cooldown - dt).max(0.0);
+        self.woof_time += dt;
+
         match self.dog_state {
             DogState::Chasing  => {},
             DogState::Blinking(t) => {
@@ -97,42 +245,73 @@ impl Dog {
         }
     }
 
-    pub fn woof(&mut self) {
-        self.woof_sound.play();
-    }
-
     // NOTE: This is similar to Cat::try_move, but lets you move a little further out of the bounds.
-    pub fn try_move(&mut self, bounds: &Vector2<u32>, change: Vector2<f32>) {
-        let (min_x, max_x) = (0.0, bounds.x as f32);
-        let (min_y, may_y) = (0.0, bounds.y as f32);
-
-        // Clamp new_pos to min and max values.
-        let mut new_pos = self.pos + change;
-        new_pos.x = if new_pos.x < min_x {
-            min_x
-        } else if new_pos.x > max_x {
-            max_x
-        } else {
-            new_pos.x
-        };
-        new_pos.y = if new_pos.y < min_y {
-            min_y
-        } else if new_pos.y > may_y {
-            may_y
-        } else {
-            new_pos.y
-        };
-
-        self.pos = new_pos;
+    pub fn try_move(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], change: Vector2<f32>) {
+        let max = bounds.cast::<f32>();
+        self.pos = settle(self.pos + change, Vector2::zero(), max, obstacles);
     }
 }
 
+/// Which set of animations a cat is drawn with.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum CatType {
     Basic,
     Kitten,
     Fat,
 }
 
+#[derive(Clone, Deserialize)]
+pub struct CatSounds {
+    pub meow: String,
+    /// One of these is picked at random for each cat.
+    pub angry: Vec<String>,
+}
+
+/// A kind of cat as described in the cats file.
+#[derive(Clone, Deserialize)]
+pub struct CatArchetype {
+    pub name: String,
+    pub animations: CatType,
+    pub sounds: CatSounds,
+    pub speed: f32,
+    /// How close the dog has to get before the cat runs away.
+    pub flee_radius: f32,
+    /// Multiplies speed while fleeing.
+    pub flee_scalar: f32,
+    pub annoyance_rate: f32,
+    pub calming_rate: f32,
+    /// Radius of the random walk when idle.
+    pub rw_radius: f32,
+    /// Seconds between meows.
+    pub meow_interval: f32,
+    /// Range the meow timer starts in, so cats don't all meow at once.
+    pub meow_start: (f32, f32),
+    /// Multiplies how far barks knock the cat away.
+    #[serde(default = "default_woof_response")]
+    pub woof_response: f32,
+    /// Chance per second that a restless penned cat escapes. Cats that don't say never escape.
+    #[serde(default)]
+    pub escape_chance: f32,
+    /// Seconds a penned cat waits with no dog watching before it gets restless.
+    #[serde(default = "default_patience")]
+    pub patience: f32,
+}
+
+fn default_woof_response() -> f32 {
+    1.0
+}
+
+fn default_patience() -> f32 {
+    10.0
+}
+
+impl CatArchetype {
+    pub fn load_all(path: &str) -> Vec<CatArchetype> {
+        let text = fs::read_to_string(path).expect("Error on reading cat archetypes.");
+        ron::de::from_str(&text).expect("Error on parsing cat archetypes.")
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum CatState {
     Flee,
@@ -140,11 +319,13 @@ pub enum CatState {
     InPen,
     Jittering,
     Cannonballing,
+    Escaping,
 }
 
 pub struct Cat {
     pub pos: Vector2<f32>,
     pub facing: Facing,
+    pub kind: String, // name of the cat's archetype
     pub cat_type: CatType,
     pub radius: f32,
     pub speed: f32,
@@ -159,106 +340,61 @@ pub struct Cat {
     pub jitter_origin: Vector2<f32>,
     pub targeting_time: f32,
     pub dog_target: Vector2<f32>,
+    pub annoyed_by: usize, // index of the dog the cat last fled from, and will cannonball at
     pub cannonballing_time: f32,
     pub flee_scalar: f32,
+    pub woof_response: f32,
+    pub impulse: Vector2<f32>, // knockback from barks, on top of the cat's own movement
+    pub escape_chance: f32,
+    pub patience: f32,
+    pub lonely_time: f32, // seconds penned with no dog watching
+    pub escape_time: f32,
     pub color: [f32; 3],
     pub meow_interval: f32,
     pub meow_time: f32,
-    pub meow_sound: Sound,
-    pub meow_sound_angry: Sound,
+    pub meow_clip: String,
+    pub angry_meow_clip: String,
+    pub pen: Option<usize>, // index of the cat box we're in, when InPen
 }
 
 impl Cat {
-    pub fn new_basic_cat(pos: Vector2<f32>, vel: Vector2<f32>) -> Self {
-        let mut rng = rand::thread_rng();
-        let meow_range = Uniform::new(-3.0, 2.0);
-        Cat {
-            pos: pos,
-            facing: Facing::Left, // TODO: Randomize!
-            cat_type: CatType::Basic,
-            radius: 70.0,
-            speed: BASIC_CAT_SPEED,
-            size: cgmath::vec2(30.0, 30.0),
-            annoyance_total: 0.0,
-            annoyance_rate: BASIC_CAT_ANNOYANCE_RATE,
-            calming_rate: BASIC_CAT_CALMING_RATE,
-            state: CatState::Idle,
-            velocity: vel,
-            rw_radius: BASIC_CAT_RW_RADIUS,
-            rw_theta: 0.0,
-            jitter_origin: pos,
-            targeting_time: 0.0,
-            dog_target: cgmath::vec2(0.0, 0.0),
-            cannonballing_time: 0.0,
-            flee_scalar: BASIC_CAT_FLEE_SCALAR,
-            meow_interval: 3.0,
-            meow_time: meow_range.sample(&mut rng),
-            meow_sound: Sounds::basic_meow(),
-            meow_sound_angry: Sounds::angry_meow(),
-
-            color: *CAT_COLORS.choose(&mut rng).unwrap(),
-        }
-    }
-
-    pub fn new_kitten(pos: Vector2<f32>, vel: Vector2<f32>) -> Self {
-        let mut rng = rand::thread_rng();
-        let meow_range = Uniform::new(-3.0, 2.0);
-
-        Cat {
-            pos: pos,
-            facing: Facing::Left, // TODO: Randomize!
-            cat_type: CatType::Kitten,
-            radius: 70.0,
-            speed: KITTEN_SPEED,
-            size: cgmath::vec2(30.0, 30.0),
-            annoyance_total: 0.0,
-            annoyance_rate: KITTEN_ANNOYANCE_RATE,
-            calming_rate: KITTEN_CALMING_RATE,
-            state: CatState::Idle,
-            velocity: vel,
-            rw_radius: KITTEN_RW_RADIUS,
-            rw_theta: 0.0,
-            jitter_origin: pos,
-            targeting_time: 0.0,
-            dog_target: cgmath::vec2(0.0, 0.0),
-            cannonballing_time: 0.0,
-            flee_scalar: KITTEN_FLEE_SCALAR,
-            meow_interval: 3.0,
-            meow_time: meow_range.sample(&mut rng),
-            meow_sound: Sounds::kitten_meow(),
-            meow_sound_angry: Sounds::angry_meow(),
-            color: *CAT_COLORS.choose(&mut rng).unwrap(),
-        }
-    }
-
-    pub fn new_fat_cat(pos: Vector2<f32>, vel: Vector2<f32>) -> Self {
-        let mut rng = rand::thread_rng();
-        let meow_range = Uniform::new(-1.0, 1.0);
+    pub fn new<R: Rng>(archetype: &CatArchetype, pos: Vector2<f32>, vel: Vector2<f32>, rng: &mut R) -> Self {
+        let meow_range = Uniform::new(archetype.meow_start.0, archetype.meow_start.1);
+        let angry_meow = archetype.sounds.angry.choose(rng).unwrap_or(&archetype.sounds.meow);
 
         Cat {
             pos: pos,
             facing: Facing::Left, // TODO: Randomize!
-            cat_type: CatType::Fat,
-            radius: 70.0,
-            speed: FAT_CAT_SPEED,
+            kind: archetype.name.clone(),
+            cat_type: archetype.animations,
+            radius: archetype.flee_radius,
+            speed: archetype.speed,
             size: cgmath::vec2(30.0, 30.0),
             annoyance_total: 0.0,
-            annoyance_rate: FAT_CAT_ANNOYANCE_RATE,
-            calming_rate: FAT_CAT_CALMING_RATE,
+            annoyance_rate: archetype.annoyance_rate,
+            calming_rate: archetype.calming_rate,
             state: CatState::Idle,
             velocity: vel,
-            rw_radius: FAT_CAT_RW_RADIUS,
+            rw_radius: archetype.rw_radius,
             rw_theta: 0.0,
             jitter_origin: pos,
             targeting_time: 0.0,
             dog_target: cgmath::vec2(0.0, 0.0),
+            annoyed_by: 0,
             cannonballing_time: 0.0,
-            flee_scalar: FAT_CAT_FLEE_SCALAR,
-            meow_interval: 3.0,
-            meow_time: meow_range.sample(&mut rng),
-            meow_sound: Sounds::fat_meow(),
-            meow_sound_angry: Sounds::angry_meow(),
-            color: *CAT_COLORS.choose(&mut rng).unwrap(),
+            flee_scalar: archetype.flee_scalar,
+            woof_response: archetype.woof_response,
+            impulse: Vector2::zero(),
+            escape_chance: archetype.escape_chance,
+            patience: archetype.patience,
+            lonely_time: 0.0,
+            escape_time: 0.0,
+            meow_interval: archetype.meow_interval,
+            meow_time: meow_range.sample(rng),
+            meow_clip: archetype.sounds.meow.clone(),
+            angry_meow_clip: angry_meow.clone(),
+            color: *CAT_COLORS.choose(rng).unwrap(),
+            pen: None,
         }
     }
 
@@ -289,22 +425,30 @@ impl Cat {
         return self.annoyance_total / ANNOYANCE_THRESHOLD
     }
 
-    pub fn jitter(&mut self, dt: f32, dog: &Dog) {
-        let mut rng = rand::thread_rng();
+    pub fn jitter<R: Rng>(&mut self, dt: f32, dogs: &[Dog], rng: &mut R) {
         let x_range = Uniform::new(-JITTER_AMOUNT, JITTER_AMOUNT);
         let y_range = Uniform::new(-JITTER_AMOUNT, JITTER_AMOUNT);
 
-        let x = x_range.sample(&mut rng);
-        let y = y_range.sample(&mut rng);
+        let x = x_range.sample(rng);
+        let y = y_range.sample(rng);
 
         self.pos.x = self.jitter_origin.x + x;
         self.pos.y = self.jitter_origin.y + y;
 
-        self.cannonball_countdown(dt, dog);
+        self.cannonball_countdown(dt, &dogs[self.annoyed_by]);
     }
 
-    pub fn update_state(&mut self, dog: &Dog, cat_box: &CatBox) -> CatState {
-        let dog_to_cat = self.pos - dog.pos;
+    /// The index of the closest dog that's near enough to scare the cat.
+    fn scariest_dog(&self, dogs: &[Dog]) -> Option<usize> {
+        (0..dogs.len())
+            .filter(|&i| dogs[i].dog_state == DogState::Chasing && self.pos.distance(dogs[i].pos) < self.radius)
+            .min_by(|&a, &b| {
+                self.pos.distance2(dogs[a].pos).partial_cmp(&self.pos.distance2(dogs[b].pos)).unwrap()
+            })
+    }
+
+    pub fn update_state(&mut self, dogs: &[Dog], pen: Option<usize>) -> CatState {
+        let scariest_dog = self.scariest_dog(dogs);
 
         match &self.cat_type {
             _ => { },
@@ -319,33 +463,43 @@ impl Cat {
             CatState::Cannonballing
         } else if self.state != CatState::Cannonballing && self.annoyance_total >= ANNOYANCE_THRESHOLD {
             CatState::Jittering
-        } else if cat_box.in_bounds(&self.pos) {
+        } else if self.state == CatState::Escaping && pen.is_some() && self.escape_time > 0.0 {
+            CatState::Escaping
+        } else if pen.is_some() {
             CatState::InPen
-        } else if dog.dog_state == DogState::Chasing && dog_to_cat.magnitude() < self.radius {
+        } else if let Some(dog) = scariest_dog {
+            self.annoyed_by = dog;
             CatState::Flee
         } else {
             CatState::Idle
         };
 
+        self.pen = if self.state == CatState::InPen {
+            pen
+        } else {
+            None
+        };
+
         self.state
     }
 
-    pub fn flee(&mut self, bounds: &Vector2<u32>, dir: &Vector2<f32>, dt: f32) {
+    /// Runs along `dir`, getting annoyed `annoyance_scalar` times as fast as usual.
+    pub fn flee(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], dir: &Vector2<f32>, annoyance_scalar: f32,
+                dt: f32) {
         match &self.cat_type {
             _ => { },
         }
 
         let speed = self.speed * self.flee_scalar;
         self.velocity = dir.normalize() * speed;
-        self.try_move(bounds, dir.normalize() * speed * dt);
-        self.increase_annoyance(dt);
+        self.try_move(bounds, obstacles, dir.normalize() * speed * dt);
+        self.increase_annoyance(dt * annoyance_scalar);
     }
 
-    pub fn idle(&mut self, bounds: &Vector2<u32>, cat_box: &CatBox, dt: f32) {
+    pub fn idle<R: Rng>(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], cat_boxes: &[CatBox], dt: f32, rng: &mut R) {
         let range_theta = Uniform::new(-0.3, 0.3);
-        let mut rng = rand::thread_rng();
         // random update rw_theta
-        self.rw_theta = self.rw_theta + range_theta.sample(&mut rng);
+        self.rw_theta = self.rw_theta + range_theta.sample(rng);
 
         // 'circle' vector by (velocity rotated by theta).normalized * rw_radius
         let t = self.rw_theta;
@@ -361,45 +515,136 @@ impl Cat {
         if (self.velocity + circle_vector).magnitude() != 0.0 {
             self.velocity = (self.velocity + circle_vector).normalize() * self.speed / 3.0;
         }
-        // apply repulsive force if we're close to the cat box
-        let box_to_cat = self.pos - cat_box.pos;
-        if box_to_cat.magnitude() < (cat_box.size.x + self.radius) {
-            self.velocity = (self.velocity + box_to_cat.normalize() * 150.0 / box_to_cat.magnitude()).normalize() * self.speed / 3.0;
+        // apply repulsive force if we're close to a cat box
+        for cat_box in cat_boxes {
+            let box_to_cat = self.pos - cat_box.pos;
+            if box_to_cat.magnitude() < (cat_box.size.x + self.radius) {
+                self.velocity = (self.velocity + box_to_cat.normalize() * 150.0 / box_to_cat.magnitude()).normalize() * self.speed / 3.0;
+            }
         }
         v = self.velocity;
-        self.try_move(bounds, v * dt);
+        self.try_move(bounds, obstacles, v * dt);
         self.decrease_annoyance(dt);
     }
 
-    pub fn in_pen(&mut self, _bounds: &Vector2<u32>, dt: f32) {
-        // TODO: wander in random direction
-        // self.pos = self.pos;
+    /// Ambles around inside `cat_box`. Cats that are still annoyed, or have been left alone too
+    /// long, might escape.
+    pub fn in_pen<R: Rng>(&mut self, cat_box: &CatBox, watched: bool, dt: f32, rng: &mut R) {
+        let speed = self.speed / 8.0;
+        let room = cat_box.size * 0.5 - self.size * 0.25;
+        let offset = self.pos - cat_box.pos;
+        if offset.x.abs() > room.x || offset.y.abs() > room.y {
+            // Cats that just ran in are still near the edge, so head further in first.
+            self.rw_theta = (-offset.y).atan2(-offset.x);
+        } else {
+            let range_theta = Uniform::new(-0.3, 0.3);
+            self.rw_theta += range_theta.sample(rng);
+            // Bounce off the walls of the box.
+            let next = offset + cgmath::vec2(self.rw_theta.cos(), self.rw_theta.sin()) * speed * dt;
+            if next.x.abs() > room.x {
+                self.rw_theta = PI - self.rw_theta;
+            }
+            if next.y.abs() > room.y {
+                self.rw_theta = -self.rw_theta;
+            }
+        }
+        self.velocity = cgmath::vec2(self.rw_theta.cos(), self.rw_theta.sin()) * speed;
+        self.pos += self.velocity * dt;
         self.decrease_annoyance(dt);
+
+        self.lonely_time = if watched {
+            0.0
+        } else {
+            self.lonely_time + dt
+        };
+        let restless = self.annoyance_total >= ESCAPE_ANNOYANCE || self.lonely_time >= self.patience;
+        if restless && rng.gen::<f32>() < self.escape_chance * dt {
+            self.start_escape(cat_box, rng);
+        }
+    }
+
+    fn start_escape<R: Rng>(&mut self, cat_box: &CatBox, rng: &mut R) {
+        let away = self.pos - cat_box.pos;
+        let dir = if away.is_zero() {
+            let theta = rng.gen::<f32>() * 2.0 * PI;
+            cgmath::vec2(theta.cos(), theta.sin())
+        } else {
+            away.normalize()
+        };
+        self.state = CatState::Escaping;
+        self.pen = None;
+        self.velocity = dir * self.speed * self.flee_scalar;
+        self.escape_time = ESCAPE_TIME;
+        self.lonely_time = 0.0;
     }
 
-    pub fn cannonball(&mut self, bounds: &Vector2<u32>, dt: f32, dog: &mut Dog) {
+    /// Runs for the edge of the pen it's escaping from.
+    pub fn escape(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], dt: f32) {
+        let v = self.velocity;
+        self.try_move(bounds, obstacles, v * dt);
+        self.escape_time -= dt;
+    }
+
+    pub fn cannonball(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], dt: f32, dogs: &mut [Dog],
+                      sound_events: &mut Vec<SoundEvent>) {
         let target = self.dog_target;
         let v = target * CANNONBALL_SPEED* dt;
         self.velocity = v;
-        self.try_move(bounds, v);
+        self.try_move(bounds, obstacles, v);
 
         self.cannonballing_time -= dt;
 
-        if self.collides_with(dog) {
-            dog.hit();
+        for dog in dogs {
+            if self.collides_with(dog) {
+                dog.hit(target);
+                sound_events.push(SoundEvent::Yip(dog.player));
+            }
         }
     }
 
-    pub fn meow(&mut self) {
-        match self.state {
-            CatState::Jittering => {
-                self.meow_time = 0.0;
-                self.meow_sound_angry.play();
-            }
-            _ => {
-                self.meow_time = 0.0;
-                self.meow_sound.play();
-            }
+    /// Starts the meow timer over. Returns whether this meow is an angry one.
+    pub fn meow(&mut self) -> bool {
+        self.meow_time = 0.0;
+        self.state == CatState::Jittering
+    }
+
+    /// Knocks the cat away from a bark at `from` and annoys it. Cats closer to the dog get knocked
+    /// further.
+    pub fn scare(&mut self, from: Vector2<f32>, dog: usize) {
+        let away = self.pos - from;
+        let dir = if away.is_zero() {
+            cgmath::vec2(1.0, 0.0)
+        } else {
+            away.normalize()
+        };
+        let closeness = 1.0 - (away.magnitude() / WOOF_RADIUS).min(1.0);
+        self.impulse += dir * WOOF_IMPULSE * self.woof_response * (0.5 + 0.5 * closeness);
+        self.annoyed_by = dog;
+
+        let was_annoyed = self.annoyance_total >= ANNOYANCE_THRESHOLD;
+        self.annoyance_total += WOOF_ANNOYANCE * self.annoyance_rate;
+        if !was_annoyed && self.annoyance_total >= ANNOYANCE_THRESHOLD {
+            self.start_jitter();
+        }
+    }
+
+    /// Annoys a penned cat with a bark, without knocking it around the box.
+    pub fn pester(&mut self, dog: usize) {
+        self.annoyed_by = dog;
+        let pestered = (self.annoyance_total + PEN_WOOF_ANNOYANCE * self.annoyance_rate).min(PEN_MAX_ANNOYANCE);
+        self.annoyance_total = self.annoyance_total.max(pestered);
+    }
+
+    /// Carries the cat along with any bark that knocked it, until the knockback wears off.
+    pub fn apply_impulse(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], dt: f32) {
+        if self.impulse.is_zero() {
+            return;
+        }
+        let change = self.impulse * dt;
+        self.try_move(bounds, obstacles, change);
+        self.impulse *= (-IMPULSE_DECAY * dt).exp();
+        if self.impulse.magnitude2() < 1.0 {
+            self.impulse = Vector2::zero();
         }
     }
 
@@ -408,29 +653,10 @@ impl Cat {
         self.state = CatState::Idle;
     }
 
-    fn try_move(&mut self, bounds: &Vector2<u32>, change: Vector2<f32>) {
+    fn try_move(&mut self, bounds: &Vector2<u32>, obstacles: &[Obstacle], change: Vector2<f32>) {
         let half_size = self.size * 0.5;
-        let (min_x, max_x) = (half_size.x, bounds.x as f32 - half_size.x);
-        let (min_y, may_y) = (half_size.y, bounds.y as f32 - half_size.y);
-
-        // Clamp new_pos to min and max values.
-        let mut new_pos = self.pos + change;
-        new_pos.x = if new_pos.x < min_x {
-            min_x
-        } else if new_pos.x > max_x {
-            max_x
-        } else {
-            new_pos.x
-        };
-        new_pos.y = if new_pos.y < min_y {
-            min_y
-        } else if new_pos.y > may_y {
-            may_y
-        } else {
-            new_pos.y
-        };
-
-        self.pos = new_pos;
+        let max = bounds.cast::<f32>() - half_size;
+        self.pos = settle(self.pos + change, half_size, max, obstacles);
     }
 
     fn decrease_annoyance(&mut self, dt: f32) {
@@ -455,12 +681,198 @@ impl Cat {
     }
 }
 
+/// A piece of furniture that blocks the dog and cats.
+pub enum Obstacle {
+    /// A rectangle centered at `pos`.
+    Rect {
+        pos: Vector2<f32>,
+        size: Vector2<f32>,
+    },
+    Circle {
+        pos: Vector2<f32>,
+        radius: f32,
+    },
+}
+
+impl Obstacle {
+    /// Whether a circle at `point` overlaps this obstacle.
+    pub fn overlaps(&self, point: Vector2<f32>, radius: f32) -> bool {
+        self.push_out(point, radius) != point
+    }
+
+    /// Moves a circle at `point` the shortest distance it takes to no longer overlap this
+    /// obstacle.
+    pub fn push_out(&self, point: Vector2<f32>, radius: f32) -> Vector2<f32> {
+        let unbounded = cgmath::vec2(f32::INFINITY, f32::INFINITY);
+        self.push_out_within(point, radius, -unbounded, unbounded)
+    }
+
+    /// Like `push_out`, but circles stuck inside a rectangle leave through the nearest side that
+    /// keeps them within `min`..`max`, so furniture against a wall doesn't push them out of the
+    /// level.
+    pub fn push_out_within(&self, point: Vector2<f32>, radius: f32, min: Vector2<f32>, max: Vector2<f32>) -> Vector2<f32> {
+        match *self {
+            Obstacle::Rect { pos, size } => {
+                let half_size = size * 0.5;
+                let top_left = pos - half_size;
+                let bottom_right = pos + half_size;
+                let closest = cgmath::vec2(point.x.max(top_left.x).min(bottom_right.x),
+                                           point.y.max(top_left.y).min(bottom_right.y));
+                if closest == point {
+                    // The center is inside the rectangle, push out through the nearest side.
+                    let exits = [
+                        (point.x - top_left.x, cgmath::vec2(top_left.x - radius, point.y)),
+                        (bottom_right.x - point.x, cgmath::vec2(bottom_right.x + radius, point.y)),
+                        (point.y - top_left.y, cgmath::vec2(point.x, top_left.y - radius)),
+                        (bottom_right.y - point.y, cgmath::vec2(point.x, bottom_right.y + radius)),
+                    ];
+                    let in_bounds = |exit: Vector2<f32>| {
+                        exit.x >= min.x && exit.x <= max.x && exit.y >= min.y && exit.y <= max.y
+                    };
+                    let nearest = |a: &&(f32, Vector2<f32>), b: &&(f32, Vector2<f32>)| {
+                        a.0.partial_cmp(&b.0).expect("Error on comparing obstacle sides")
+                    };
+                    exits.iter()
+                        .filter(|&&(_, exit)| in_bounds(exit))
+                        .min_by(nearest)
+                        .or_else(|| exits.iter().min_by(nearest))
+                        .map(|&(_, exit)| exit)
+                        .expect("Error on finding an obstacle side")
+                } else {
+                    let offset = point - closest;
+                    if offset.magnitude() < radius {
+                        closest + offset.normalize() * radius
+                    } else {
+                        point
+                    }
+                }
+            }
+            Obstacle::Circle { pos, radius: obstacle_radius } => {
+                let offset = point - pos;
+                let min_distance = obstacle_radius + radius;
+                if offset.magnitude() >= min_distance {
+                    point
+                } else if offset.magnitude() == 0.0 {
+                    pos + cgmath::vec2(min_distance, 0.0)
+                } else {
+                    pos + offset.normalize() * min_distance
+                }
+            }
+        }
+    }
+}
+
+/// A scripted path for a cat box to follow, starting from wherever the box starts.
+pub enum BoxPath {
+    /// Back and forth between the start and `to`.
+    Patrol {
+        to: Vector2<f32>,
+        speed: f32,
+    },
+    /// Around `center`, once every `period` seconds.
+    Circle {
+        center: Vector2<f32>,
+        period: f32,
+    },
+    /// Through each of `points` in turn, then back to the start.
+    Waypoints {
+        points: Vec<Vector2<f32>>,
+        speed: f32,
+    },
+}
+
+impl BoxPath {
+    /// Where a box that started at `start` is after following the path for `time` seconds.
+    fn pos_at(&self, start: Vector2<f32>, time: f32) -> Vector2<f32> {
+        match *self {
+            BoxPath::Patrol { to, speed } => {
+                let length = start.distance(to);
+                if length == 0.0 {
+                    return start;
+                }
+                let traveled = (time * speed) % (2.0 * length);
+                let distance = if traveled < length {
+                    traveled
+                } else {
+                    2.0 * length - traveled
+                };
+                start + (to - start).normalize() * distance
+            }
+            BoxPath::Circle { center, period } => {
+                let offset = start - center;
+                let angle = offset.y.atan2(offset.x) + 2.0 * PI * time / period;
+                center + cgmath::vec2(angle.cos(), angle.sin()) * offset.magnitude()
+            }
+            BoxPath::Waypoints { ref points, speed } => {
+                let mut stops = vec![start];
+                stops.extend(points.iter().cloned());
+                stops.push(start);
+                let length: f32 = stops.windows(2).map(|leg| leg[0].distance(leg[1])).sum();
+                if length == 0.0 {
+                    return start;
+                }
+                let mut traveled = (time * speed) % length;
+                for leg in stops.windows(2) {
+                    let leg_length = leg[0].distance(leg[1]);
+                    if traveled <= leg_length && leg_length > 0.0 {
+                        return leg[0] + (leg[1] - leg[0]).normalize() * traveled;
+                    }
+                    traveled -= leg_length;
+                }
+                start
+            }
+        }
+    }
+}
+
 pub struct CatBox {
     pub pos: Vector2<f32>,
     pub size: Vector2<f32>,
+    pub start_pos: Vector2<f32>,
+    pub path: Option<BoxPath>,
+    pub path_time: f32,
+    /// Only take cats of this archetype.
+    pub accepts: Option<String>,
+    /// Only take cats of this color, as an index into CAT_COLORS.
+    pub color: Option<usize>,
+    /// How many cats the box needs. Defaults to every cat it takes.
+    pub quota: Option<u32>,
+    /// The player whose box this is in versus mode.
+    pub owner: Option<usize>,
+
+    pub scored: u32,
+    pub needed: u32,
 }
 
 impl CatBox {
+    pub fn is_filtered(&self) -> bool {
+        self.accepts.is_some() || self.color.is_some()
+    }
+
+    pub fn accepts(&self, cat: &Cat) -> bool {
+        self.accepts.as_ref().map_or(true, |kind| *kind == cat.kind) &&
+            self.color.map_or(true, |color| CAT_COLORS.get(color) == Some(&cat.color))
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.scored >= self.needed
+    }
+
+    pub fn reset(&mut self) {
+        self.pos = self.start_pos;
+        self.path_time = 0.0;
+    }
+
+    /// Moves the box along its path, returning how far it moved.
+    pub fn update(&mut self, dt: f32) -> Vector2<f32> {
+        let old_pos = self.pos;
+        if let Some(ref path) = self.path {
+            self.path_time += dt;
+            self.pos = path.pos_at(self.start_pos, self.path_time);
+        }
+        self.pos - old_pos
+    }
+
     pub fn in_bounds(&self, point: &Vector2<f32>) -> bool {
         let half_size = self.size * 0.5;
         let top_left = self.pos - half_size;
@@ -472,6 +884,61 @@ impl CatBox {
 
 pub struct Camera {
     pub pos: Vector2<f32>,
-    pub bounds: Vector2<f32>,
+    pub bounds: Vector2<f32>, // size of the view at zoom 1, in game units
     pub zoom: i32,
 }
+
+impl Camera {
+    /// How much of the level the camera sees, in game units.
+    pub fn view_size(&self) -> Vector2<f32> {
+        self.bounds / self.zoom as f32
+    }
+
+    /// Moves `pos` so the view stays inside the level, centering the level along any axis it's
+    /// smaller than the view on.
+    fn clamp(&self, pos: Vector2<f32>, level_bounds: &Vector2<u32>) -> Vector2<f32> {
+        let half_view = self.view_size() * 0.5;
+        let clamp_axis = |pos: f32, half_view: f32, level_size: f32| {
+            if level_size <= half_view * 2.0 {
+                level_size / 2.0
+            } else {
+                pos.max(half_view).min(level_size - half_view)
+            }
+        };
+        cgmath::vec2(clamp_axis(pos.x, half_view.x, level_bounds.x as f32),
+                     clamp_axis(pos.y, half_view.y, level_bounds.y as f32))
+    }
+
+    /// Eases towards `target`, without showing anything past the level's edges.
+    pub fn update(&mut self, target: Vector2<f32>, level_bounds: &Vector2<u32>, dt: f32) {
+        let goal = self.clamp(target, level_bounds);
+        self.pos += (goal - self.pos) * (1.0 - (-CAMERA_FOLLOW_RATE * dt).exp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn furniture_against_a_wall_keeps_the_dog_in_the_level() {
+        // Level 5's table along the top wall.
+        let table = Obstacle::Rect { pos: cgmath::vec2(220.0, 15.0), size: cgmath::vec2(70.0, 30.0) };
+        let mut dog = Dog::new(0, cgmath::vec2(220.0, 10.0));
+        dog.try_move(&cgmath::vec2(400, 300), &[table], Vector2::zero());
+        assert_eq!(dog.pos, cgmath::vec2(220.0, 30.0 + BODY_RADIUS));
+    }
+
+    #[test]
+    fn pushing_out_of_one_obstacle_doesnt_land_in_another() {
+        // A plant pot beside a crate. Leaving the pot lands in the crate, and leaving the crate
+        // lands back in the pot.
+        let obstacles = [
+            Obstacle::Circle { pos: cgmath::vec2(72.0, 106.0), radius: 8.0 },
+            Obstacle::Rect { pos: cgmath::vec2(100.0, 100.0), size: cgmath::vec2(40.0, 40.0) },
+        ];
+        let mut dog = Dog::new(0, cgmath::vec2(82.0, 100.0));
+        dog.try_move(&cgmath::vec2(400, 300), &obstacles, Vector2::zero());
+        assert!(!obstacles.iter().any(|obstacle| obstacle.overlaps(dog.pos, BODY_RADIUS - 0.01)));
+    }
+}
@@ -0,0 +1,407 @@
+use cgmath::{self, Vector2};
+use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use midgar::{KeyCode, Midgar};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::KeyBindings;
+
+pub const MAX_PLAYERS: usize = 2;
+
+/// Everything a player can do, independent of what device they do it with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveX,
+    MoveY,
+    Woof,
+    Sprint,
+    Confirm,
+    Back,
+    Restart,
+    NextLevel,
+    /// Gives up on the current level and moves on. Only on the keyboard, so nobody hits it by
+    /// accident reaching for back.
+    SkipLevel,
+    Versus,
+    Pause,
+    /// Quits from the title screen. Only on the keyboard, so pressing Start there doesn't quit.
+    Quit,
+    MenuUp,
+    MenuDown,
+}
+
+/// A device, or anything pretending to be one, that players drive actions with.
+pub trait InputSource {
+    /// How far `player` is pushing an axis action, from -1.0 to 1.0.
+    fn axis(&self, player: usize, action: Action) -> f32;
+    /// Whether `player` pressed a button action this tick.
+    fn was_pressed(&self, player: usize, action: Action) -> bool;
+    /// Whether `player` is holding a button action down.
+    fn is_held(&self, player: usize, action: Action) -> bool;
+}
+
+pub struct KeyboardSource<'a> {
+    midgar: &'a Midgar,
+    bindings: &'a KeyBindings,
+}
+
+impl<'a> KeyboardSource<'a> {
+    pub fn new(midgar: &'a Midgar, bindings: &'a KeyBindings) -> Self {
+        KeyboardSource {
+            midgar,
+            bindings,
+        }
+    }
+
+    fn key_axis(&self, negative: KeyCode, positive: KeyCode) -> f32 {
+        let input = self.midgar.input();
+        match (input.is_key_held(negative), input.is_key_held(positive)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+impl<'a> InputSource for KeyboardSource<'a> {
+    fn axis(&self, player: usize, action: Action) -> f32 {
+        let keys = &self.bindings.players[player];
+        match action {
+            Action::MoveX => self.key_axis(keys.left, keys.right),
+            Action::MoveY => self.key_axis(keys.up, keys.down),
+            _ => 0.0,
+        }
+    }
+
+    fn was_pressed(&self, player: usize, action: Action) -> bool {
+        let input = self.midgar.input();
+        let keys = &self.bindings.players[player];
+        match action {
+            Action::Woof => input.was_key_pressed(keys.woof),
+            Action::MenuUp => input.was_key_pressed(keys.up),
+            Action::MenuDown => input.was_key_pressed(keys.down),
+            // Escape always pauses, so nobody can rebind their way out of the pause menu.
+            Action::Pause | Action::Quit => player == 0 && input.was_key_pressed(KeyCode::Escape),
+            // The menu keys count as player 1's.
+            _ => player == 0 && self.bindings.menu.key_for(action).map_or(false, |key| input.was_key_pressed(key)),
+        }
+    }
+
+    fn is_held(&self, player: usize, action: Action) -> bool {
+        let keys = &self.bindings.players[player];
+        match action {
+            Action::Sprint => self.midgar.input().is_key_held(keys.sprint),
+            _ => false,
+        }
+    }
+}
+
+/// Gamepad buttons and the actions they trigger. A button can trigger more than one action.
+const PAD_BUTTONS: &[(Button, Action)] = &[
+    (Button::South, Action::Woof),
+    (Button::South, Action::Confirm),
+    (Button::RightTrigger, Action::Sprint),
+    (Button::East, Action::Back),
+    (Button::West, Action::Restart),
+    (Button::North, Action::NextLevel),
+    (Button::Select, Action::Versus),
+    (Button::Start, Action::Pause),
+    (Button::DPadUp, Action::MenuUp),
+    (Button::DPadDown, Action::MenuDown),
+];
+
+/// The gamepad input the game cares about, stripped of everything device specific.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadEvent {
+    Pressed(Button),
+    Released(Button),
+    Axis(Axis, f32),
+}
+
+#[derive(Default)]
+struct PadState {
+    stick_x: f32,
+    stick_y: f32,
+    held: Vec<Button>,
+    pressed: Vec<Button>,
+}
+
+impl PadState {
+    fn is_held(&self, button: Button) -> bool {
+        self.held.contains(&button)
+    }
+}
+
+/// Gamepads, one per player in the order they were first used. Fed by `Gamepads`, or directly
+/// through `handle` to fake a gamepad.
+#[derive(Default)]
+pub struct GamepadSource {
+    pads: Vec<PadState>,
+}
+
+impl GamepadSource {
+    pub fn handle(&mut self, pad: usize, event: PadEvent) {
+        while self.pads.len() <= pad {
+            self.pads.push(PadState::default());
+        }
+        let state = &mut self.pads[pad];
+        match event {
+            PadEvent::Pressed(button) => {
+                state.pressed.push(button);
+                state.held.push(button);
+            }
+            PadEvent::Released(button) => state.held.retain(|&held| held != button),
+            PadEvent::Axis(Axis::LeftStickX, value) => state.stick_x = value,
+            // Up is positive on gamepads, but down is positive in the game.
+            PadEvent::Axis(Axis::LeftStickY, value) => state.stick_y = -value,
+            PadEvent::Axis(..) => {}
+        }
+    }
+
+    /// Forgets this tick's button presses.
+    pub fn end_tick(&mut self) {
+        for state in &mut self.pads {
+            state.pressed.clear();
+        }
+    }
+}
+
+impl InputSource for GamepadSource {
+    fn axis(&self, player: usize, action: Action) -> f32 {
+        let state = match self.pads.get(player) {
+            Some(state) => state,
+            None => return 0.0,
+        };
+        let (stick, negative, positive) = match action {
+            Action::MoveX => (state.stick_x, Button::DPadLeft, Button::DPadRight),
+            Action::MoveY => (state.stick_y, Button::DPadUp, Button::DPadDown),
+            _ => return 0.0,
+        };
+        if state.is_held(negative) && !state.is_held(positive) {
+            -1.0
+        } else if state.is_held(positive) && !state.is_held(negative) {
+            1.0
+        } else {
+            stick
+        }
+    }
+
+    fn was_pressed(&self, player: usize, action: Action) -> bool {
+        self.pads.get(player).map_or(false, |state| {
+            PAD_BUTTONS.iter()
+                .any(|&(button, button_action)| button_action == action && state.pressed.contains(&button))
+        })
+    }
+
+    fn is_held(&self, player: usize, action: Action) -> bool {
+        self.pads.get(player).map_or(false, |state| {
+            PAD_BUTTONS.iter()
+                .any(|&(button, button_action)| button_action == action && state.is_held(button))
+        })
+    }
+}
+
+/// Reads connected gamepads with gilrs.
+pub struct Gamepads {
+    gilrs: Option<Gilrs>,
+    ids: Vec<GamepadId>,
+    pub source: GamepadSource,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        // Not having gamepads shouldn't stop anyone from playing with the keyboard.
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                eprintln!("Error on initializing gamepads: {}", e);
+                None
+            }
+        };
+        Gamepads {
+            gilrs,
+            ids: Vec::new(),
+            source: GamepadSource::default(),
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.source.end_tick();
+        let gilrs = match self.gilrs {
+            Some(ref mut gilrs) => gilrs,
+            None => return,
+        };
+        while let Some(Event { id, event, .. }) = gilrs.next_event() {
+            let event = match event {
+                EventType::ButtonPressed(button, _) => PadEvent::Pressed(button),
+                EventType::ButtonReleased(button, _) => PadEvent::Released(button),
+                EventType::AxisChanged(axis, value, _) => PadEvent::Axis(axis, value),
+                _ => continue,
+            };
+            // Gamepads keep their player for the whole session, even if they get unplugged.
+            let pad = match self.ids.iter().position(|&known| known == id) {
+                Some(pad) => pad,
+                None => {
+                    self.ids.push(id);
+                    self.ids.len() - 1
+                }
+            };
+            self.source.handle(pad, event);
+        }
+    }
+}
+
+/// One player's input for a single tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub move_x: f32,
+    pub move_y: f32,
+    pub woof: bool,
+    pub sprint: bool,
+}
+
+impl PlayerInput {
+    /// Movement from -1.0 to 1.0 on each axis. Sticks can push less than all the way.
+    pub fn move_dir(&self) -> Vector2<f32> {
+        cgmath::vec2(self.move_x, self.move_y)
+    }
+}
+
+/// Everything the world needs to know about the players' input for a single tick.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputState {
+    pub players: [PlayerInput; MAX_PLAYERS],
+    pub restart: bool,
+    pub back: bool,
+    pub confirm: bool,
+    pub next_level: bool,
+    pub skip_level: bool,
+    pub versus: bool,
+    pub pause: bool,
+    pub quit: bool,
+    pub menu_up: bool,
+    pub menu_down: bool,
+}
+
+impl InputState {
+    /// Combines every source's actions. Axes take whichever source pushes furthest, and menu
+    /// actions can come from any player.
+    pub fn poll(sources: &[&dyn InputSource]) -> Self {
+        let axis = |player, action| {
+            sources.iter()
+                .map(|source| source.axis(player, action))
+                .fold(0.0f32, |furthest, value| if value.abs() > furthest.abs() { value } else { furthest })
+        };
+        let pressed = |player, action| sources.iter().any(|source| source.was_pressed(player, action));
+        let held = |player, action| sources.iter().any(|source| source.is_held(player, action));
+        let any_pressed = |action| (0..MAX_PLAYERS).any(|player| pressed(player, action));
+        let player_input = |player| PlayerInput {
+            move_x: axis(player, Action::MoveX),
+            move_y: axis(player, Action::MoveY),
+            woof: pressed(player, Action::Woof),
+            sprint: held(player, Action::Sprint),
+        };
+
+        InputState {
+            players: [player_input(0), player_input(1)],
+            restart: any_pressed(Action::Restart),
+            back: any_pressed(Action::Back),
+            confirm: any_pressed(Action::Confirm),
+            next_level: any_pressed(Action::NextLevel),
+            skip_level: any_pressed(Action::SkipLevel),
+            versus: any_pressed(Action::Versus),
+            pause: any_pressed(Action::Pause),
+            quit: any_pressed(Action::Quit),
+            menu_up: any_pressed(Action::MenuUp),
+            menu_down: any_pressed(Action::MenuDown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A keyboard that holds whatever the test says.
+    #[derive(Default)]
+    struct FakeKeys {
+        move_x: f32,
+        pressed: Vec<(usize, Action)>,
+    }
+
+    impl InputSource for FakeKeys {
+        fn axis(&self, player: usize, action: Action) -> f32 {
+            if player == 0 && action == Action::MoveX { self.move_x } else { 0.0 }
+        }
+
+        fn was_pressed(&self, player: usize, action: Action) -> bool {
+            self.pressed.contains(&(player, action))
+        }
+
+        fn is_held(&self, _player: usize, _action: Action) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn sticks_move_part_way() {
+        let mut pads = GamepadSource::default();
+        pads.handle(0, PadEvent::Axis(Axis::LeftStickX, 0.5));
+        pads.handle(0, PadEvent::Axis(Axis::LeftStickY, 0.25));
+        let input = InputState::poll(&[&pads]);
+        assert_eq!(input.players[0].move_x, 0.5);
+        assert_eq!(input.players[0].move_y, -0.25);
+        assert_eq!(input.players[1].move_x, 0.0);
+    }
+
+    #[test]
+    fn d_pad_overrides_the_stick() {
+        let mut pads = GamepadSource::default();
+        pads.handle(0, PadEvent::Axis(Axis::LeftStickX, 0.3));
+        pads.handle(0, PadEvent::Pressed(Button::DPadLeft));
+        assert_eq!(pads.axis(0, Action::MoveX), -1.0);
+        // Both directions at once cancel out, leaving the stick.
+        pads.handle(0, PadEvent::Pressed(Button::DPadRight));
+        assert_eq!(pads.axis(0, Action::MoveX), 0.3);
+        pads.handle(0, PadEvent::Released(Button::DPadLeft));
+        assert_eq!(pads.axis(0, Action::MoveX), 1.0);
+    }
+
+    #[test]
+    fn presses_last_one_tick() {
+        let mut pads = GamepadSource::default();
+        pads.handle(1, PadEvent::Pressed(Button::South));
+        pads.handle(1, PadEvent::Pressed(Button::RightTrigger));
+        assert!(pads.was_pressed(1, Action::Woof));
+        assert!(pads.was_pressed(1, Action::Confirm));
+        assert!(!pads.was_pressed(0, Action::Woof));
+
+        pads.end_tick();
+        assert!(!pads.was_pressed(1, Action::Woof));
+        assert!(pads.is_held(1, Action::Sprint));
+        pads.handle(1, PadEvent::Released(Button::RightTrigger));
+        assert!(!pads.is_held(1, Action::Sprint));
+    }
+
+    #[test]
+    fn poll_merges_keyboard_and_pads() {
+        let keys = FakeKeys {
+            move_x: -1.0,
+            pressed: vec![(0, Action::Woof), (0, Action::Restart)],
+        };
+        let mut pads = GamepadSource::default();
+        pads.handle(0, PadEvent::Axis(Axis::LeftStickX, 0.5));
+        pads.handle(1, PadEvent::Axis(Axis::LeftStickX, 0.75));
+        pads.handle(1, PadEvent::Pressed(Button::South));
+
+        let input = InputState::poll(&[&keys, &pads]);
+        // The keyboard pushes further than player 1's stick.
+        assert_eq!(input.players[0].move_x, -1.0);
+        assert!(input.players[0].woof);
+        assert_eq!(input.players[1].move_x, 0.75);
+        assert!(input.players[1].woof);
+        // Menu actions count from anyone.
+        assert!(input.restart);
+        assert!(input.confirm);
+        assert!(!input.back);
+    }
+}
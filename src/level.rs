@@ -1,98 +1,369 @@
+use std::collections::BTreeMap;
+use std::fs;
+
 use cgmath::{self, InnerSpace, MetricSpace, Vector2};
-use rand::{self, Rng};
+use rand::Rng;
 use rand::distributions::{Distribution, Uniform};
-use crate::config;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
 
 use crate::entities::*;
+use crate::input::MAX_PLAYERS;
+
+// How many random spots to try for each cat before giving up on the level.
+const MAX_SPAWN_TRIES: u32 = 1000;
+
+#[derive(Clone, Deserialize)]
+pub enum BoxPathDef {
+    Patrol {
+        to: (f32, f32),
+        speed: f32,
+    },
+    Circle {
+        center: (f32, f32),
+        period: f32,
+    },
+    Waypoints {
+        points: Vec<(f32, f32)>,
+        speed: f32,
+    },
+}
+
+impl BoxPathDef {
+    fn to_box_path(&self) -> BoxPath {
+        match *self {
+            BoxPathDef::Patrol { to, speed } => BoxPath::Patrol {
+                to: cgmath::vec2(to.0, to.1),
+                speed,
+            },
+            BoxPathDef::Circle { center, period } => BoxPath::Circle {
+                center: cgmath::vec2(center.0, center.1),
+                period,
+            },
+            BoxPathDef::Waypoints { ref points, speed } => BoxPath::Waypoints {
+                points: points.iter().map(|&(x, y)| cgmath::vec2(x, y)).collect(),
+                speed,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CatBoxDef {
+    pub pos: (f32, f32),
+    pub size: (f32, f32),
+    #[serde(default)]
+    pub path: Option<BoxPathDef>,
+    #[serde(default)]
+    pub accepts: Option<String>,
+    #[serde(default)]
+    pub color: Option<usize>,
+    #[serde(default)]
+    pub quota: Option<u32>,
+    #[serde(default)]
+    pub owner: Option<usize>,
+}
+
+impl CatBoxDef {
+    fn to_cat_box(&self) -> CatBox {
+        CatBox {
+            pos: cgmath::vec2(self.pos.0, self.pos.1),
+            size: cgmath::vec2(self.size.0, self.size.1),
+            start_pos: cgmath::vec2(self.pos.0, self.pos.1),
+            path: self.path.as_ref().map(BoxPathDef::to_box_path),
+            path_time: 0.0,
+            accepts: self.accepts.clone(),
+            color: self.color,
+            quota: self.quota,
+            owner: self.owner,
+            scored: 0,
+            needed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub enum ObstacleDef {
+    /// A rectangle given by its center and size.
+    Rect {
+        pos: (f32, f32),
+        size: (f32, f32),
+    },
+    Circle {
+        pos: (f32, f32),
+        radius: f32,
+    },
+}
+
+impl ObstacleDef {
+    fn to_obstacle(&self) -> Obstacle {
+        match *self {
+            ObstacleDef::Rect { pos, size } => Obstacle::Rect {
+                pos: cgmath::vec2(pos.0, pos.1),
+                size: cgmath::vec2(size.0, size.1),
+            },
+            ObstacleDef::Circle { pos, radius } => Obstacle::Circle {
+                pos: cgmath::vec2(pos.0, pos.1),
+                radius,
+            },
+        }
+    }
+}
+
+/// A rectangle cats can spawn in, given by its top-left corner and size.
+#[derive(Clone, Deserialize)]
+pub struct SpawnRegion {
+    pub pos: (f32, f32),
+    pub size: (f32, f32),
+}
 
-pub const MAX_LEVEL: u32 = 5;
+/// A level as described in the levels file.
+#[derive(Clone, Deserialize)]
+pub struct LevelDef {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub bounds: (u32, u32),
+    pub cat_boxes: Vec<CatBoxDef>,
+    /// How many cats of each archetype to spawn, by archetype name.
+    pub cats: BTreeMap<String, u32>,
+    #[serde(default)]
+    pub spawn_regions: Vec<SpawnRegion>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDef>,
+    /// Seconds to beat for three and two stars.
+    #[serde(default)]
+    pub par_times: Option<(f32, f32)>,
+    /// How long a versus match in this level lasts, in seconds.
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+}
+
+impl LevelDef {
+    pub fn load_all(path: &str) -> Vec<LevelDef> {
+        let text = fs::read_to_string(path).expect("Error on reading levels.");
+        let levels: Vec<LevelDef> = ron::de::from_str(&text).expect("Error on parsing levels.");
+        assert!(!levels.is_empty(), "No levels in {}", path);
+        levels
+    }
+
+    /// Makes sure the level only refers to cats, colors and players that exist.
+    pub fn check(&self, archetypes: &[CatArchetype]) -> Result<(), String> {
+        if self.cat_boxes.is_empty() {
+            return Err("no cat boxes".to_string());
+        }
+        for cat_box in &self.cat_boxes {
+            if let Some(color) = cat_box.color {
+                if color >= CAT_COLORS.len() {
+                    return Err(format!("a cat box with unknown color {}", color));
+                }
+            }
+            if let Some(owner) = cat_box.owner {
+                if owner >= MAX_PLAYERS {
+                    return Err(format!("a cat box owned by unknown player {}", owner));
+                }
+            }
+        }
+        for name in self.cats.keys() {
+            if !archetypes.iter().any(|archetype| &archetype.name == name) {
+                return Err(format!("unknown cat archetype {}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks every level in the file at `path`, so mistakes show up at startup instead of when the
+/// level is reached.
+pub fn check_levels(path: &str, levels: &[LevelDef], archetypes: &[CatArchetype]) -> Result<(), String> {
+    for (i, level) in levels.iter().enumerate() {
+        level.check(archetypes).map_err(|e| format!("Level {} in {} has {}", i + 1, path, e))?;
+    }
+    Ok(())
+}
 
 pub struct Level {
-    pub cat_box: CatBox,
-    pub num_cats: u32,
+    pub cat_boxes: Vec<CatBox>,
     pub bounds: Vector2<u32>,
     pub level_num: u32,
-    pub cats: (u32, u32, u32),
+    pub name: Option<String>,
+    pub cats: BTreeMap<String, u32>,
+    pub spawn_regions: Vec<SpawnRegion>,
+    pub obstacles: Vec<Obstacle>,
+    pub par_times: Option<(f32, f32)>,
+    pub time_limit: Option<f32>,
 }
 
 impl Level {
-    pub fn new(level_num: u32) -> Self {
-        let num_cats = Level::num_cats_for_level(level_num);
-        let cats = Level::cats_for_level(level_num);
+    /// Levels are numbered from 1. Numbers past the end of `levels` load the last level.
+    pub fn new(levels: &[LevelDef], level_num: u32) -> Self {
+        let level_num = level_num.max(1).min(levels.len() as u32);
+        let def = &levels[level_num as usize - 1];
+        let cats = def.cats.clone();
 
         Level {
-            level_num: level_num,
-            cat_box: CatBox {
-                pos: cgmath::vec2(100.0, 100.0),
-                size: cgmath::vec2(60.0, 60.0),
-            },
-            num_cats: num_cats,
-            bounds: cgmath::vec2(config::GAME_SIZE.x, config::GAME_SIZE.y),
-            cats: cats,
-            
+            level_num,
+            cat_boxes: def.cat_boxes.iter().map(CatBoxDef::to_cat_box).collect(),
+            bounds: cgmath::vec2(def.bounds.0, def.bounds.1),
+            name: def.name.clone(),
+            cats,
+            spawn_regions: def.spawn_regions.clone(),
+            obstacles: def.obstacles.iter().map(ObstacleDef::to_obstacle).collect(),
+            par_times: def.par_times,
+            time_limit: def.time_limit,
         }
     }
 
-    pub fn next_level(&mut self) {
-        let next_level_num = self.level_num + 1;
-        self.level_num = next_level_num;
-        self.num_cats = Level::num_cats_for_level(next_level_num);
-        self.cats = Level::cats_for_level(next_level_num);
+    pub fn next_level(&mut self, levels: &[LevelDef]) {
+        *self = Level::new(levels, self.level_num + 1);
+    }
+
+    /// Where the dog starts out.
+    pub fn start_pos(&self) -> Vector2<f32> {
+        self.cat_boxes[0].start_pos
     }
 
-    pub fn cats_for_level(level_num: u32) -> (u32, u32, u32) {
-        match level_num {
-            1 => (1, 0, 0),
-            2 => (2, 1, 0),
-            3 => (3, 2, 0),
-            4 => (5, 3, 2),
-            5 => (10, 6, 4),
-            _ => (1, 0, 0)
+    /// The index of the box `player` owns in versus mode, if any.
+    pub fn box_for_player(&self, player: usize) -> Option<usize> {
+        self.cat_boxes.iter().position(|cat_box| cat_box.owner == Some(player))
+    }
+
+    /// Whether the cat counts towards the box at `index`. Boxes without a filter take any cat no
+    /// filtered box wants.
+    pub fn box_accepts(&self, index: usize, cat: &Cat) -> bool {
+        let cat_box = &self.cat_boxes[index];
+        if cat_box.is_filtered() {
+            cat_box.accepts(cat)
+        } else {
+            !self.cat_boxes.iter().any(|other| other.is_filtered() && other.accepts(cat))
         }
     }
 
-    pub fn num_cats_for_level(level_num: u32) -> u32 {
-        let (x, y, z) = Level::cats_for_level(level_num);
-        return x + y + z;
+    /// The index of the box the cat is penned in, if any.
+    pub fn pen_for(&self, cat: &Cat) -> Option<usize> {
+        (0..self.cat_boxes.len())
+            .find(|&i| self.cat_boxes[i].in_bounds(&cat.pos) && self.box_accepts(i, cat))
     }
 
-    pub fn generate_cats(&self) -> Vec<Cat> {
-        // Spawn cats a bit away from walls and away from the cat box.
-        let cat_box_radius = 80.0;
-        let dist_x = Uniform::new(20.0, self.bounds.x as f32 - 20.0);
-        let dist_y = Uniform::new(20.0, self.bounds.y as f32 - 20.0);
-        let mut rng = rand::thread_rng();
+    pub fn update_quotas(&mut self, cats: &[Cat]) {
+        for i in 0..self.cat_boxes.len() {
+            let takes = cats.iter().filter(|cat| self.box_accepts(i, cat)).count() as u32;
+            let cat_box = &mut self.cat_boxes[i];
+            cat_box.needed = cat_box.quota.map_or(takes, |quota| quota.min(takes));
+            cat_box.scored = 0;
+        }
+    }
 
-        let meow_range = Uniform::new(-3.0, 2.0);
+    /// Rates a finishing time from one to three stars. Levels without par times always get three.
+    pub fn stars_for(&self, time: f32) -> u32 {
+        let (three_stars, two_stars) = match self.par_times {
+            Some(par_times) => par_times,
+            None => return 3,
+        };
+        if time <= three_stars {
+            3
+        } else if time <= two_stars {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.cat_boxes.iter().all(CatBox::is_full)
+    }
+
+    fn random_spawn_pos<R: Rng>(&self, rng: &mut R) -> Vector2<f32> {
+        match self.spawn_regions.choose(rng) {
+            Some(region) => {
+                let dist_x = Uniform::new_inclusive(region.pos.0, region.pos.0 + region.size.0);
+                let dist_y = Uniform::new_inclusive(region.pos.1, region.pos.1 + region.size.1);
+                cgmath::vec2(dist_x.sample(rng), dist_y.sample(rng))
+            }
+            None => {
+                // Spawn cats a bit away from walls.
+                let dist_x = Uniform::new(20.0, self.bounds.x as f32 - 20.0);
+                let dist_y = Uniform::new(20.0, self.bounds.y as f32 - 20.0);
+                cgmath::vec2(dist_x.sample(rng), dist_y.sample(rng))
+            }
+        }
+    }
+
+    pub fn generate_cats<R: Rng>(&self, archetypes: &[CatArchetype], rng: &mut R) -> Vec<Cat> {
+        // Spawn cats away from the cat box and clear of furniture.
+        let cat_box_radius = 80.0;
+        let obstacle_margin = 20.0;
 
         let mut cats = Vec::new();
-        let mut basic_cats: u32 = 0;
-        let mut kittens: u32 = 0;
-        let mut fat_cats: u32 = 0;
+        for (name, &count) in &self.cats {
+            // check_levels has made sure every archetype exists.
+            let archetype = archetypes.iter()
+                .find(|archetype| &archetype.name == name)
+                .expect("Error on finding cat archetype.");
 
-        let (total_basic, total_kittens, _total_fat) = self.cats;
+            for _ in 0..count {
+                let mut cat_pos = self.random_spawn_pos(rng);
+                let mut tries = 1;
+                // TODO: We should probably try to space out the cats from each other.
+                while self.cat_boxes.iter().any(|cat_box| cat_pos.distance(cat_box.start_pos) < cat_box_radius) ||
+                    self.obstacles.iter().any(|obstacle| obstacle.overlaps(cat_pos, obstacle_margin)) {
+                    assert!(tries < MAX_SPAWN_TRIES,
+                            "Level {} has nowhere to spawn cats away from its boxes and furniture", self.level_num);
+                    cat_pos = self.random_spawn_pos(rng);
+                    tries += 1;
+                }
+                let vel = cgmath::vec2(rng.gen::<f32>() * 2.0 - 1.0,
+                                       rng.gen::<f32>() * 2.0 - 1.0).normalize();
 
-        for _ in 0..self.num_cats {
-            let mut cat_pos = cgmath::vec2(dist_x.sample(&mut rng), dist_y.sample(&mut rng));
-            // TODO: We should probably try to space out the cats from each other.
-            while cat_pos.distance(self.cat_box.pos) < cat_box_radius {
-                cat_pos = cgmath::vec2(dist_x.sample(&mut rng), dist_y.sample(&mut rng));
+                cats.push(Cat::new(archetype, cat_pos, vel, rng));
             }
-            let vel = cgmath::vec2(rng.gen::<f32>() * 2.0 - 1.0,
-                                   rng.gen::<f32>() * 2.0 - 1.0).normalize();
-
-            let cat = if basic_cats < total_basic {
-                basic_cats += 1;
-                Cat::new_basic_cat(cat_pos, vel)
-            } else if kittens < total_kittens {
-                kittens += 1;
-                Cat::new_kitten(cat_pos, vel)
-            } else {
-                fat_cats += 1;
-                Cat::new_fat_cat(cat_pos, vel)
-            };
-            cats.push(cat);
         }
         cats
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use super::*;
+    use crate::world::tests::test_archetypes;
+
+    fn one_box_level(cat_box: &str) -> Vec<LevelDef> {
+        let text = format!("[(bounds: (200, 200), cat_boxes: [{}], cats: {{}})]", cat_box);
+        ron::de::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn level_data_is_checked() {
+        let archetypes = test_archetypes();
+        let check = |cat_box: &str| check_levels("test", &one_box_level(cat_box), &archetypes);
+        assert_eq!(check("(pos: (20.0, 20.0), size: (40.0, 40.0), color: Some(3), owner: Some(1))"), Ok(()));
+        assert_eq!(check("(pos: (20.0, 20.0), size: (40.0, 40.0), color: Some(7))"),
+                   Err("Level 1 in test has a cat box with unknown color 7".to_string()));
+        assert_eq!(check("(pos: (20.0, 20.0), size: (40.0, 40.0), owner: Some(2))"),
+                   Err("Level 1 in test has a cat box owned by unknown player 2".to_string()));
+
+        let mut levels = one_box_level("(pos: (20.0, 20.0), size: (40.0, 40.0))");
+        levels[0].cats.insert("bsic".to_string(), 1);
+        assert_eq!(check_levels("test", &levels, &archetypes),
+                   Err("Level 1 in test has unknown cat archetype bsic".to_string()));
+        levels[0].cat_boxes.clear();
+        assert_eq!(check_levels("test", &levels, &archetypes),
+                   Err("Level 1 in test has no cat boxes".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "nowhere to spawn cats")]
+    fn level_without_room_for_cats_fails_to_load() {
+        // The table covers the whole level.
+        let levels: Vec<LevelDef> = ron::de::from_str(r#"[(
+            bounds: (200, 200),
+            cat_boxes: [(pos: (20.0, 20.0), size: (40.0, 40.0))],
+            cats: {"basic": 1},
+            obstacles: [Rect(pos: (100.0, 100.0), size: (200.0, 200.0))],
+        )]"#).unwrap();
+        let level = Level::new(&levels, 1);
+        level.generate_cats(&test_archetypes(), &mut XorShiftRng::seed_from_u64(0));
+    }
+}
@@ -1,16 +1,28 @@
 extern crate cgmath;
 extern crate midgar;
 extern crate rand;
+extern crate rand_xorshift;
+#[cfg(feature = "audio")]
 extern crate ears;
+extern crate serde;
+extern crate ron;
+extern crate dirs;
+extern crate gilrs;
+extern crate sdl2;
 
 mod app;
+mod bindings;
 mod config;
 mod entities;
+mod input;
 mod level;
 mod renderer;
+mod replay;
 mod world;
 mod sounds;
 mod party;
+mod save;
+mod settings;
 
 fn main() {
     let app_config = midgar::MidgarAppConfig::new()
@@ -1,5 +1,5 @@
 use cgmath::{self, Vector2};
-use rand::{self, Rng};
+use rand::Rng;
 use rand::distributions::{Distribution, Standard, Uniform};
 use rand::seq::SliceRandom;
 
@@ -35,24 +35,23 @@ pub struct PartyItem {
 }
 
 impl PartyItem {
-    fn new() -> Self {
-        let mut rng = rand::thread_rng();
+    fn new<R: Rng>(rng: &mut R) -> Self {
         let rotation_range = Uniform::new(0.0, 359.0);
         let x_range = Uniform::new(0.0, 800.0);
         let y_range = Uniform::new(-700.0, 0.0);
 
         PartyItem {
             kind: rng.gen::<PartyItemKind>(),
-            color: *CAT_COLORS.choose(&mut rng).unwrap(),
-            rotation: rotation_range.sample(&mut rng),
-            pos: cgmath::vec2(x_range.sample(&mut rng),
-                              y_range.sample(&mut rng)),
+            color: *CAT_COLORS.choose(rng).unwrap(),
+            rotation: rotation_range.sample(rng),
+            pos: cgmath::vec2(x_range.sample(rng),
+                              y_range.sample(rng)),
             flip: rng.gen(),
             //rot_dir: 
         }
     }
 
-    fn update(&mut self, dt: f32) {
+    fn update<R: Rng>(&mut self, dt: f32, rng: &mut R) {
         // TODO: Update rotation
         self.rotation += match self.kind {
             PartyItemKind::BasicCat => 180.0 * dt,
@@ -65,12 +64,11 @@ impl PartyItem {
 
         // TODO: Wrap around once hit the bottom of the screen.
         if self.pos.y > config::SCREEN_SIZE.y as f32 + 50.0 {
-            let mut rng = rand::thread_rng();
             let rotation_range = Uniform::new(0.0, 359.0);
             let x_range = Uniform::new(0.0, 800.0);
-            self.pos.x = x_range.sample(&mut rng);
+            self.pos.x = x_range.sample(rng);
             self.pos.y = -50.0;
-            self.rotation = rotation_range.sample(&mut rng);
+            self.rotation = rotation_range.sample(rng);
         }
     }
 }
@@ -80,10 +78,10 @@ pub struct Party {
 }
 
 impl Party {
-    pub fn new() -> Self {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
         let mut party_items = Vec::with_capacity(NUM_ITEMS as usize);
         for _ in 0..NUM_ITEMS {
-            party_items.push(PartyItem::new());
+            party_items.push(PartyItem::new(rng));
         }
 
         Party {
@@ -91,9 +89,9 @@ impl Party {
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
+    pub fn update<R: Rng>(&mut self, dt: f32, rng: &mut R) {
         for item in &mut self.party_items {
-            item.update(dt);
+            item.update(dt, rng);
         }
     }
 }
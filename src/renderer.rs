@@ -1,8 +1,9 @@
+use std::f32::consts::PI;
 use std::rc::Rc;
 
 use cgmath::{self, Matrix4};
 use cgmath::prelude::*;
-use crate::entities::{Camera, DogState, CatState, CatType};
+use crate::entities::{Camera, Dog, DogState, CatState, CatType, Obstacle, DOG_HEALTH};
 use midgar::{Midgar, Surface};
 use midgar::graphics::animation::{Animation, PlayMode};
 use midgar::graphics::shape::ShapeRenderer;
@@ -10,11 +11,48 @@ use midgar::graphics::text::{self, Font, TextRenderer};
 use midgar::graphics::sprite::{DrawTexture, MagnifySamplerFilter, SamplerWrapFunction, SpriteDrawParams, SpriteRenderer};
 use midgar::graphics::texture::TextureRegion;
 
+use crate::bindings::{BindingsMenu, KeyBindings};
 use crate::config;
 use crate::entities::{CAT_COLORS, Facing};
+use crate::input::MAX_PLAYERS;
 use crate::party::PartyItemKind;
+use crate::settings::{SettingsMenu, SETTINGS_OPTIONS};
 use crate::world::*;
 
+const FURNITURE_COLOR: [f32; 3] = [0.45, 0.3, 0.2];
+// The size of the scoring area cat_box.png is drawn around. The rug is bigger than that, and
+// scales with the box.
+const BOX_HIT_SIZE: f32 = 60.0;
+// Largest the minimap gets, in screen pixels.
+const MINIMAP_SIZE: (f32, f32) = (160.0, 112.0);
+const MINIMAP_BOX_COLOR: [f32; 3] = [0.6, 0.45, 0.3];
+const MINIMAP_DOG_COLORS: [[f32; 3]; MAX_PLAYERS] = [
+    [0.3, 0.6, 1.0],
+    [1.0, 0.5, 0.9],
+];
+// How far in from the screen's edge off-screen cat arrows are drawn.
+const ARROW_MARGIN: f32 = 20.0;
+// Tints each player's dog so they can tell them apart.
+const PLAYER_TINTS: [[f32; 3]; MAX_PLAYERS] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 0.75, 0.6],
+];
+// How many horizontal strips to draw circles with.
+const CIRCLE_STRIPS: u32 = 16;
+// How many dots to draw rings with.
+const RING_DOTS: u32 = 32;
+const WOOF_COLOR: [f32; 3] = [1.0, 0.95, 0.6];
+// Width of the bark cooldown bar under each dog.
+const WOOF_BAR_WIDTH: f32 = 24.0;
+const STAMINA_BAR_SIZE: (f32, f32) = (120.0, 8.0);
+const HEALTH_COLOR: [f32; 3] = [0.9, 0.2, 0.3];
+
+/// Full screen menus the app draws instead of the game.
+pub enum Menu<'m> {
+    Bindings(&'m BindingsMenu),
+    Settings(&'m SettingsMenu),
+}
+
 pub struct GameRenderer<'a> {
     projection: Matrix4<f32>,
     sprite: SpriteRenderer,
@@ -25,6 +63,7 @@ pub struct GameRenderer<'a> {
     how_to_play: TextureRegion,
 
     background: TextureRegion,
+    dim: TextureRegion,
     cat_box: TextureRegion,
     basic_cat_walk_animation: Animation,
     basic_cat_idle_animation: Animation,
@@ -68,6 +107,12 @@ impl<'a> GameRenderer<'a> {
             TextureRegion::with_sub_field(texture, (0, 0), (config::SCREEN_SIZE.x, config::SCREEN_SIZE.y))
         };
 
+        // A single translucent black pixel, stretched over the screen to dim it.
+        let dim = {
+            let texture = Rc::new(midgar.graphics().load_texture("assets/dim.png", false));
+            TextureRegion::new(texture)
+        };
+
         let cat_box = {
             let texture = Rc::new(midgar.graphics().load_texture("assets/cat_box.png", false));
             TextureRegion::new(texture)
@@ -215,6 +260,7 @@ impl<'a> GameRenderer<'a> {
             how_to_play: how_to_play,
 
             background: background,
+            dim,
             cat_box: cat_box,
             basic_cat_walk_animation: basic_cat_walk_animation,
             basic_cat_idle_animation: basic_cat_idle_animation,
@@ -240,8 +286,12 @@ impl<'a> GameRenderer<'a> {
         }
     }
 
-    pub fn render(&mut self, midgar: &Midgar, dt: f32, world: &GameWorld, camera: &Camera) {
-        self.game_time += dt;
+    pub fn render(&mut self, midgar: &Midgar, dt: f32, world: &GameWorld, camera: &Camera,
+                  bindings: &KeyBindings, menu: Option<Menu>) {
+        // Keep everything still while paused.
+        if world.game_state != GameState::Paused {
+            self.game_time += dt;
+        }
 
         // Get framebuffer target.
         let mut target = midgar.graphics().display().draw();
@@ -251,6 +301,15 @@ impl<'a> GameRenderer<'a> {
             .magnify_filter(MagnifySamplerFilter::Nearest)
             .alpha(true);
 
+        if let Some(menu) = menu {
+            match menu {
+                Menu::Bindings(menu) => self.draw_bindings_menu(menu, &mut target),
+                Menu::Settings(menu) => self.draw_settings_menu(menu, &mut target),
+            }
+            target.finish().unwrap();
+            return;
+        }
+
         match world.game_state {
             GameState::StartMenu => {
                 let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
@@ -260,11 +319,19 @@ impl<'a> GameRenderer<'a> {
                 // Draw start menu splash screen!
                 self.sprite.draw(&self.start_menu.draw(config::SCREEN_SIZE.x as f32 / 2.0, config::SCREEN_SIZE.y as f32 / 2.0),
                                  draw_params, &mut target);
+                self.draw_join_text(world, &projection, &mut target);
+                let text = format!("Press {} for versus!\nPress {} for settings",
+                                   bindings.menu.versus.name(), bindings.menu.settings.name());
+                self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
+                                    30, 452.0, 432.0, 500, &projection, &mut target);
+                self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
+                                    30, 450.0, 430.0, 500, &projection, &mut target);
                 // Draw blinking text!
                 if self.game_time.fract() < 0.5 {
-                    self.text.draw_text("Press Enter to play!", &self.font, [0.0, 0.0, 0.0],
+                    let text = format!("Press {} to play!", bindings.menu.confirm.name());
+                    self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
                                         40, 452.0, 542.0, 500, &projection, &mut target);
-                    self.text.draw_text("Press Enter to play!", &self.font, [1.0, 1.0, 1.0],
+                    self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
                                         40, 450.0, 540.0, 500, &projection, &mut target);
                 }
             },
@@ -319,9 +386,10 @@ impl<'a> GameRenderer<'a> {
 
                 // Draw blinking text!
                 if self.game_time.fract() < 0.5 {
-                    self.text.draw_text("Press Tab to return!", &self.font, [0.0, 0.0, 0.0],
+                    let text = format!("Press {} to return!", bindings.menu.back.name());
+                    self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
                                         40, 452.0, 542.0, 500, &projection, &mut target);
-                    self.text.draw_text("Press Tab to return!", &self.font, [1.0, 1.0, 1.0],
+                    self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
                                         40, 450.0, 540.0, 500, &projection, &mut target);
                 }
             },
@@ -359,17 +427,32 @@ impl<'a> GameRenderer<'a> {
                 sprite.set_color(CAT_COLORS[2].into());
                 self.sprite.draw(&sprite, draw_params, &mut target);
 
+                self.draw_join_text(world, &projection, &mut target);
                 // Draw blinking text!
                 if self.game_time.fract() < 0.5 {
-                    self.text.draw_text("Press Enter to play!", &self.font, [0.0, 0.0, 0.0],
+                    let text = format!("Press {} to play!", bindings.menu.confirm.name());
+                    self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
                                         40, 452.0, 542.0, 500, &projection, &mut target);
-                    self.text.draw_text("Press Enter to play!", &self.font, [1.0, 1.0, 1.0],
+                    self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
                                         40, 450.0, 540.0, 500, &projection, &mut target);
                 }
             },
-            GameState::Running | GameState::Won => {
+            GameState::Running | GameState::Won | GameState::Failed => {
+                self.draw_world(dt, world, camera, &mut target);
+                self.draw_ui(dt, world, camera, bindings, &mut target);
+            },
+            GameState::Versus | GameState::VersusOver => {
+                self.draw_world(dt, world, camera, &mut target);
+                self.draw_versus_ui(world, camera, bindings, &mut target);
+            },
+            GameState::Paused => {
                 self.draw_world(dt, world, camera, &mut target);
-                self.draw_ui(dt, world, &mut target);
+                if world.versus.is_some() {
+                    self.draw_versus_ui(world, camera, bindings, &mut target);
+                } else {
+                    self.draw_ui(dt, world, camera, bindings, &mut target);
+                }
+                self.draw_pause_menu(world, &mut target);
             },
             GameState::GameOver => {
                 self.draw_world(dt, world, camera, &mut target);
@@ -400,10 +483,11 @@ impl<'a> GameRenderer<'a> {
                 self.sprite.draw(&sprite, draw_params, &mut target);
 
                 // Draw win text!
-                let text = "You are the most magical corgi in all the land!\nPress R to start anew!";
-                self.text.draw_text(text, &self.font, [0.0, 0.0, 0.0],
+                let text = format!("You are the most magical corgi in all the land!\nPress {} to start anew!",
+                                   bindings.menu.restart.name());
+                self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
                                     40, 22.0, 502.0, 800, &projection, &mut target);
-                self.text.draw_text(text, &self.font, [1.0, 1.0, 1.0],
+                self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
                                     40, 20.0, 500.0, 800, &projection, &mut target);
             },
         }
@@ -411,14 +495,107 @@ impl<'a> GameRenderer<'a> {
         target.finish().unwrap();
     }
 
+    fn draw_bindings_menu<S: Surface>(&mut self, menu: &BindingsMenu, target: &mut S) {
+        let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
+                                       config::SCREEN_SIZE.y as f32, 0.0,
+                                       -1.0, 1.0);
+        let title = "Keys\nUp/Down: choose  Enter: rebind  Backspace: defaults  Escape: done";
+        self.text.draw_text(title, &self.font, [1.0, 1.0, 1.0],
+                            30, 20.0, 10.0, 800, &projection, target);
+
+        let bindings = &menu.bindings;
+        for slot in 0..bindings.slot_count() {
+            let key_text = if menu.waiting && slot == menu.selected {
+                "Press a key...".to_string()
+            } else {
+                bindings.slot_key(slot).name()
+            };
+            let text = format!("{}: {}", bindings.slot_label(slot), key_text);
+            let color = if slot == menu.selected {
+                [1.0, 0.9, 0.3]
+            } else {
+                [1.0, 1.0, 1.0]
+            };
+            self.text.draw_text(&text, &self.font, color,
+                                30, 60.0, 80.0 + slot as f32 * 33.0, 700, &projection, target);
+        }
+    }
+
+    fn draw_settings_menu<S: Surface>(&mut self, menu: &SettingsMenu, target: &mut S) {
+        let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
+                                       config::SCREEN_SIZE.y as f32, 0.0,
+                                       -1.0, 1.0);
+        let title = "Settings\nUp/Down: choose  Left/Right: change  Enter: select  Escape: done";
+        self.text.draw_text(title, &self.font, [1.0, 1.0, 1.0],
+                            30, 20.0, 10.0, 800, &projection, target);
+
+        for (i, &option) in SETTINGS_OPTIONS.iter().enumerate() {
+            let color = if i == menu.selected {
+                [1.0, 0.9, 0.3]
+            } else {
+                [1.0, 1.0, 1.0]
+            };
+            self.text.draw_text(&menu.settings.label(option), &self.font, color,
+                                30, 60.0, 100.0 + i as f32 * 45.0, 700, &projection, target);
+        }
+    }
+
+    fn draw_pause_menu<S: Surface>(&mut self, world: &GameWorld, target: &mut S) {
+        let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
+                                       config::SCREEN_SIZE.y as f32, 0.0,
+                                       -1.0, 1.0);
+        let draw_params = SpriteDrawParams::new()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .alpha(true);
+
+        // Dim the frozen world behind the menu.
+        self.sprite.set_projection_matrix(projection);
+        let mut sprite = self.dim.draw(config::SCREEN_SIZE.x as f32 / 2.0, config::SCREEN_SIZE.y as f32 / 2.0);
+        sprite.set_scale(config::SCREEN_SIZE.cast::<f32>());
+        self.sprite.draw(&sprite, draw_params, target);
+
+        let selected = world.pause_menu.as_ref().map_or(0, |menu| menu.selected);
+        self.text.draw_text("Paused", &self.font, [1.0, 1.0, 1.0],
+                            60, 320.0, 150.0, 400, &projection, target);
+        for (i, &option) in PAUSE_OPTIONS.iter().enumerate() {
+            let label = match option {
+                PauseOption::Resume => "Resume",
+                PauseOption::RestartLevel => "Restart Level",
+                PauseOption::Settings => "Settings",
+                PauseOption::MainMenu => "Main Menu",
+                PauseOption::Quit => "Quit",
+            };
+            let color = if i == selected {
+                [1.0, 0.9, 0.3]
+            } else {
+                [1.0, 1.0, 1.0]
+            };
+            self.text.draw_text(label, &self.font, color,
+                                40, 320.0, 240.0 + i as f32 * 45.0, 400, &projection, target);
+        }
+    }
+
+    fn draw_join_text<S: Surface>(&mut self, world: &GameWorld, projection: &Matrix4<f32>, target: &mut S) {
+        let text = if world.dogs.len() < MAX_PLAYERS {
+            "Player 2: bark to join!"
+        } else {
+            "Player 2 is in!"
+        };
+        self.text.draw_text(text, &self.font, [0.0, 0.0, 0.0],
+                            30, 452.0, 502.0, 500, projection, target);
+        self.text.draw_text(text, &self.font, [1.0, 1.0, 1.0],
+                            30, 450.0, 500.0, 500, projection, target);
+    }
+
     fn draw_world<S: Surface>(&mut self, dt: f32, world: &GameWorld, camera: &Camera, target: &mut S) {
         // set the camera view
         let camera_pos = camera.pos.extend(0.0);
         let view = cgmath::Matrix4::look_at(cgmath::Point3::from_vec(camera_pos),
                                             cgmath::Point3::new(0.0, 0.0, -1.0) + camera_pos,
                                             cgmath::vec3(0.0, 1.0, 0.0));
+        let zoom = cgmath::Matrix4::from_scale(camera.zoom as f32);
 
-        let combined = self.projection * view;
+        let combined = self.projection * zoom * view;
         self.sprite.set_projection_matrix(combined);
         self.shape.set_projection_matrix(combined);
 
@@ -426,20 +603,48 @@ impl<'a> GameRenderer<'a> {
             .magnify_filter(MagnifySamplerFilter::Nearest)
             .alpha(true);
 
-        // Background
+        // Background, tiled to cover the whole level.
         let pos = self.background.size();
-        let mut sprite = self.background.draw(pos.x as f32 / 2.0, pos.y as f32 / 2.0);
-        sprite.set_scale(cgmath::vec2(2.0, 2.0));
-        self.sprite.draw(&sprite,
-                        SpriteDrawParams::new()
-                            .magnify_filter(MagnifySamplerFilter::Nearest)
-                            .alpha(true)
-                            .wrap_function(SamplerWrapFunction::Repeat),
-                        target);
-
-        // Draw cat box.
-        self.sprite.draw(&self.cat_box.draw(world.cat_box().pos.x, world.cat_box().pos.y),
-                         draw_params, target);
+        let tile_size = pos.cast::<f32>() * 2.0;
+        let tiles_x = (world.level.bounds.x as f32 / tile_size.x).ceil().max(1.0) as u32;
+        let tiles_y = (world.level.bounds.y as f32 / tile_size.y).ceil().max(1.0) as u32;
+        for tile_x in 0..tiles_x {
+            for tile_y in 0..tiles_y {
+                let mut sprite = self.background.draw(pos.x as f32 / 2.0 + tile_x as f32 * tile_size.x,
+                                                      pos.y as f32 / 2.0 + tile_y as f32 * tile_size.y);
+                sprite.set_scale(cgmath::vec2(2.0, 2.0));
+                self.sprite.draw(&sprite,
+                                SpriteDrawParams::new()
+                                    .magnify_filter(MagnifySamplerFilter::Nearest)
+                                    .alpha(true)
+                                    .wrap_function(SamplerWrapFunction::Repeat),
+                                target);
+            }
+        }
+
+        // Draw furniture.
+        for obstacle in &world.level.obstacles {
+            match *obstacle {
+                Obstacle::Rect { pos, size } => {
+                    self.shape.draw_filled_rect(pos.x, pos.y, size.x, size.y, FURNITURE_COLOR, target);
+                }
+                Obstacle::Circle { pos, radius } => {
+                    self.draw_filled_circle(pos, radius, FURNITURE_COLOR, target);
+                }
+            }
+        }
+
+        // Draw cat boxes, tinted if they only take one color of cat or belong to a player.
+        for cat_box in &world.level.cat_boxes {
+            let mut sprite = self.cat_box.draw(cat_box.pos.x, cat_box.pos.y);
+            sprite.set_scale(cat_box.size / BOX_HIT_SIZE);
+            if let Some(color) = cat_box.color {
+                sprite.set_color(CAT_COLORS[color].into());
+            } else if let Some(owner) = cat_box.owner {
+                sprite.set_color(PLAYER_TINTS[owner].into());
+            }
+            self.sprite.draw(&sprite, draw_params, target);
+        }
 
         // Draw cats!
         for cat in &world.cats {
@@ -481,55 +686,298 @@ impl<'a> GameRenderer<'a> {
             self.sprite.draw(&sprite, draw_params, target);
         }
 
-        // Draw dog, woof.
-        match world.dog.dog_state {
-            DogState::Chasing | DogState::Blinking(true) => {
-                let mut sprite = if world.dog.vel.is_zero() {
-                    self.wizard_dog_idle_animation.current_key_frame(self.game_time)
-                        .draw(world.dog.pos.x, world.dog.pos.y)
-                } else {
-                    self.wizard_dog_run_animation.current_key_frame(self.game_time)
-                        .draw(world.dog.pos.x, world.dog.pos.y)
-                };
-                sprite.set_flip_x(world.dog.facing == Facing::Right);
-                self.sprite.draw(&sprite, draw_params, target);
+        // Bark waves, and how long until each dog can bark again.
+        for dog in &world.dogs {
+            if let Some(radius) = dog.woof_wave_radius() {
+                self.draw_ring(dog.pos, radius, WOOF_COLOR, target);
+            }
+            let recharge = dog.woof_recharge();
+            if recharge < 1.0 {
+                let pos = dog.pos + cgmath::vec2(0.0, dog.size.y / 2.0 + 4.0);
+                let width = WOOF_BAR_WIDTH * recharge;
+                self.shape.draw_filled_rect(pos.x, pos.y, WOOF_BAR_WIDTH, 3.0, [0.2, 0.2, 0.2], target);
+                self.shape.draw_filled_rect(pos.x - (WOOF_BAR_WIDTH - width) / 2.0, pos.y, width, 3.0, WOOF_COLOR, target);
+            }
+        }
+
+        // Draw dogs, woof.
+        for dog in &world.dogs {
+            match dog.dog_state {
+                DogState::Chasing | DogState::Blinking(true) => {
+                    let mut sprite = if dog.vel.is_zero() {
+                        self.wizard_dog_idle_animation.current_key_frame(self.game_time)
+                            .draw(dog.pos.x, dog.pos.y)
+                    } else {
+                        self.wizard_dog_run_animation.current_key_frame(self.game_time)
+                            .draw(dog.pos.x, dog.pos.y)
+                    };
+                    sprite.set_flip_x(dog.facing == Facing::Right);
+                    sprite.set_color(PLAYER_TINTS[dog.player].into());
+                    self.sprite.draw(&sprite, draw_params, target);
+                }
+                DogState::Blinking(false) => {}
             }
-            DogState::Blinking(false) => {}
         }
     }
 
+    // ShapeRenderer only does rectangles, so build circles out of horizontal strips.
+    fn draw_filled_circle<S: Surface>(&mut self, pos: cgmath::Vector2<f32>, radius: f32, color: [f32; 3], target: &mut S) {
+        let strip_height = radius * 2.0 / CIRCLE_STRIPS as f32;
+        for i in 0..CIRCLE_STRIPS {
+            let y = -radius + strip_height * (i as f32 + 0.5);
+            let width = 2.0 * (radius * radius - y * y).sqrt();
+            self.shape.draw_filled_rect(pos.x, pos.y + y, width, strip_height, color, target);
+        }
+    }
+
+    /// Draws how much stamina a dog has left, in their minimap color, with the bar's left edge at
+    /// `left`. The bar goes red while the dog is winded.
+    fn draw_stamina_bar<S: Surface>(&mut self, dog: &Dog, left: f32, y: f32, target: &mut S) {
+        let (width, height) = STAMINA_BAR_SIZE;
+        let color = if dog.winded {
+            [0.8, 0.2, 0.2]
+        } else {
+            MINIMAP_DOG_COLORS[dog.player]
+        };
+        self.shape.draw_filled_rect(left + width / 2.0, y, width + 4.0, height + 4.0, [0.0, 0.0, 0.0], target);
+        let filled = width * dog.stamina;
+        self.shape.draw_filled_rect(left + filled / 2.0, y, filled, height, color, target);
+    }
+
+    // Rings are drawn as a circle of dots.
+    fn draw_ring<S: Surface>(&mut self, pos: cgmath::Vector2<f32>, radius: f32, color: [f32; 3], target: &mut S) {
+        for i in 0..RING_DOTS {
+            let angle = i as f32 / RING_DOTS as f32 * 2.0 * PI;
+            self.shape.draw_filled_rect(pos.x + radius * angle.cos(), pos.y + radius * angle.sin(), 3.0, 3.0, color, target);
+        }
+    }
+
+    /// Draws a minimap and arrows towards off-screen cats, when the level doesn't fit on screen.
+    fn draw_navigation<S: Surface>(&mut self, world: &GameWorld, camera: &Camera, projection: Matrix4<f32>, target: &mut S) {
+        let level_size = world.level.bounds.cast::<f32>();
+        let view_size = camera.view_size();
+        if level_size.x <= view_size.x && level_size.y <= view_size.y {
+            return;
+        }
+        self.shape.set_projection_matrix(projection);
+
+        // Point at cats that are off camera from the edge of the screen.
+        let screen_size = config::SCREEN_SIZE.cast::<f32>();
+        let screen_center = screen_size * 0.5;
+        let world_to_screen = screen_size.x / view_size.x;
+        let max_offset = screen_center - cgmath::vec2(ARROW_MARGIN, ARROW_MARGIN);
+        for cat in &world.cats {
+            let offset = (cat.pos - camera.pos) * world_to_screen;
+            if offset.x.abs() <= screen_center.x && offset.y.abs() <= screen_center.y {
+                continue;
+            }
+            // Shrink the offset until it fits inside the margin, keeping its direction.
+            let fit = (max_offset.x / offset.x.abs()).min(max_offset.y / offset.y.abs());
+            let tip = screen_center + offset * fit;
+            let dir = offset.normalize();
+            let color = cat_state_color(cat.state);
+            for (i, &size) in [10.0, 7.0, 4.0].iter().enumerate() {
+                let pos = tip - dir * (i as f32 * 8.0);
+                self.shape.draw_filled_rect(pos.x, pos.y, size, size, color, target);
+            }
+        }
+
+        // Draw the minimap in the bottom left corner, scaled to fit the level.
+        let scale = (MINIMAP_SIZE.0 / level_size.x).min(MINIMAP_SIZE.1 / level_size.y);
+        let map_size = level_size * scale;
+        let map_pos = cgmath::vec2(10.0, screen_size.y - 10.0 - map_size.y);
+        let to_map = |pos: cgmath::Vector2<f32>| map_pos + pos * scale;
+        let center = to_map(level_size * 0.5);
+        self.shape.draw_filled_rect(center.x, center.y, map_size.x + 4.0, map_size.y + 4.0, [1.0, 1.0, 1.0], target);
+        self.shape.draw_filled_rect(center.x, center.y, map_size.x, map_size.y, [0.15, 0.1, 0.1], target);
+        for cat_box in &world.level.cat_boxes {
+            let pos = to_map(cat_box.pos);
+            let size = cat_box.size * scale;
+            self.shape.draw_filled_rect(pos.x, pos.y, size.x, size.y, MINIMAP_BOX_COLOR, target);
+        }
+        for cat in &world.cats {
+            let pos = to_map(cat.pos);
+            self.shape.draw_filled_rect(pos.x, pos.y, 3.0, 3.0, cat_state_color(cat.state), target);
+        }
+        for dog in &world.dogs {
+            let pos = to_map(dog.pos);
+            self.shape.draw_filled_rect(pos.x, pos.y, 5.0, 5.0, MINIMAP_DOG_COLORS[dog.player], target);
+        }
+    }
 
-    fn draw_ui<S: Surface>(&mut self, _dt: f32, world: &GameWorld, target: &mut S) {
+    fn draw_ui<S: Surface>(&mut self, _dt: f32, world: &GameWorld, camera: &Camera, bindings: &KeyBindings,
+                           target: &mut S) {
         let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
                                        config::SCREEN_SIZE.y as f32, 0.0,
                                        -1.0, 1.0);
+        self.draw_navigation(world, camera, projection, target);
         let draw_params = SpriteDrawParams::new()
             .magnify_filter(MagnifySamplerFilter::Nearest)
             .alpha(true);
 
-        // Draw cat face next to score!
+        // Draw each box's score, with a cat face tinted like the box. Make room for the names of
+        // the cats a box takes if there are any.
         self.sprite.set_projection_matrix(projection);
-        let mut sprite = self.cat_face.draw(660.0, 25.0);
-        sprite.set_scale(cgmath::vec2(3.0, 3.0));
-        self.sprite.draw(&sprite, draw_params, target);
-        // Draw score text!
-        let score_text = format!("{:02}/{:02}", world.cats_scored, world.level.num_cats);
-        self.text.draw_text(&score_text, &self.font, [0.0, 0.0, 0.0],
-                            40, 697.0, 7.0, 800, &projection, target);
-        self.text.draw_text(&score_text, &self.font, [1.0, 1.0, 1.0],
-                            40, 695.0, 5.0, 800, &projection, target);
+        let x = if world.level.cat_boxes.iter().any(|cat_box| cat_box.accepts.is_some()) {
+            560.0
+        } else {
+            660.0
+        };
+        for (i, cat_box) in world.level.cat_boxes.iter().enumerate() {
+            let y = i as f32 * 40.0;
+            let mut sprite = self.cat_face.draw(x, 25.0 + y);
+            sprite.set_scale(cgmath::vec2(3.0, 3.0));
+            if let Some(color) = cat_box.color {
+                sprite.set_color(CAT_COLORS[color].into());
+            }
+            self.sprite.draw(&sprite, draw_params, target);
+            // Draw score text!
+            let score_text = match cat_box.accepts {
+                Some(ref kind) => format!("{:02}/{:02} {}", cat_box.scored, cat_box.needed, kind),
+                None => format!("{:02}/{:02}", cat_box.scored, cat_box.needed),
+            };
+            self.text.draw_text(&score_text, &self.font, [0.0, 0.0, 0.0],
+                                40, x + 37.0, 7.0 + y, 800, &projection, target);
+            self.text.draw_text(&score_text, &self.font, [1.0, 1.0, 1.0],
+                                40, x + 35.0, 5.0 + y, 800, &projection, target);
+        }
+        // Draw level name!
+        let level_text = match world.level.name {
+            Some(ref name) => format!("Level {}: {}", world.level.level_num, name),
+            None => format!("Level {}", world.level.level_num),
+        };
+        self.text.draw_text(&level_text, &self.font, [0.0, 0.0, 0.0],
+                            40, 12.0, 7.0, 600, &projection, target);
+        self.text.draw_text(&level_text, &self.font, [1.0, 1.0, 1.0],
+                            40, 10.0, 5.0, 600, &projection, target);
+        // Draw level timer!
+        let time_text = format_time(world.level_time);
+        self.text.draw_text(&time_text, &self.font, [0.0, 0.0, 0.0],
+                            40, 12.0, 47.0, 600, &projection, target);
+        self.text.draw_text(&time_text, &self.font, [1.0, 1.0, 1.0],
+                            40, 10.0, 45.0, 600, &projection, target);
+        // Draw stamina bars under the timer!
+        self.shape.set_projection_matrix(projection);
+        for dog in &world.dogs {
+            let y = 95.0 + dog.player as f32 * 14.0;
+            self.draw_stamina_bar(dog, 12.0, y, target);
+            // And a pip for each hit the dog can still take.
+            for i in 0..DOG_HEALTH {
+                let color = if i < dog.health { HEALTH_COLOR } else { [0.3, 0.3, 0.3] };
+                self.shape.draw_filled_rect(STAMINA_BAR_SIZE.0 + 30.0 + i as f32 * 14.0, y, 10.0, 10.0, color, target);
+            }
+        }
         match world.game_state {
             GameState::Running => {
             },
             GameState::Won => {
+                // Draw the star rating as cat faces, greying out the ones that weren't earned.
+                let time = world.result.map_or(world.level_time, |result| result.time);
+                if let Some(result) = world.result {
+                    for i in 0..3 {
+                        let mut sprite = self.cat_face.draw(360.0 + i as f32 * 40.0, 465.0);
+                        sprite.set_scale(cgmath::vec2(3.0, 3.0));
+                        if i >= result.stars {
+                            sprite.set_color(cgmath::vec3(0.3, 0.3, 0.3));
+                        }
+                        self.sprite.draw(&sprite, draw_params, target);
+                    }
+                }
                 // Draw won text!
-                let text = "Cats corralled!\nPress N to start the next level";
-                self.text.draw_text(text, &self.font, [0.0, 0.0, 0.0],
+                let text = format!("Cats corralled in {}!\nPress {} to start the next level",
+                                   format_time(time), bindings.menu.next_level.name());
+                self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
+                                    40, 252.0, 502.0, 800, &projection, target);
+                self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
+                                    40, 250.0, 500.0, 800, &projection, target);
+            },
+            GameState::Failed => {
+                let text = format!("Bowled over by a cat!\nPress {} to try again", bindings.menu.restart.name());
+                self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
                                     40, 252.0, 502.0, 800, &projection, target);
-                self.text.draw_text(text, &self.font, [1.0, 1.0, 1.0],
+                self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
                                     40, 250.0, 500.0, 800, &projection, target);
             },
             _ => {},
         }
     }
+
+    fn draw_versus_ui<S: Surface>(&mut self, world: &GameWorld, camera: &Camera, bindings: &KeyBindings,
+                                  target: &mut S) {
+        let projection = cgmath::ortho(0.0, config::SCREEN_SIZE.x as f32,
+                                       config::SCREEN_SIZE.y as f32, 0.0,
+                                       -1.0, 1.0);
+        self.draw_navigation(world, camera, projection, target);
+        let draw_params = SpriteDrawParams::new()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .alpha(true);
+        let versus = match world.versus {
+            Some(ref versus) => versus,
+            None => return,
+        };
+        let scores = world.versus_scores();
+
+        // Draw each player's score at their side of the screen, with a dog tinted like theirs.
+        self.sprite.set_projection_matrix(projection);
+        for (player, score) in scores.iter().enumerate() {
+            let x = if player == 0 { 30.0 } else { 670.0 };
+            let mut sprite = self.wizard_dog_idle_animation.current_key_frame(self.game_time)
+                .draw(x, 25.0);
+            sprite.set_scale(cgmath::vec2(2.0, 2.0));
+            sprite.set_color(PLAYER_TINTS[player].into());
+            self.sprite.draw(&sprite, draw_params, target);
+            let score_text = format!("P{} {:02}", player + 1, score);
+            self.text.draw_text(&score_text, &self.font, [0.0, 0.0, 0.0],
+                                40, x + 37.0, 7.0, 800, &projection, target);
+            self.text.draw_text(&score_text, &self.font, [1.0, 1.0, 1.0],
+                                40, x + 35.0, 5.0, 800, &projection, target);
+        }
+        self.shape.set_projection_matrix(projection);
+        for dog in &world.dogs {
+            let x = if dog.player == 0 { 12.0 } else { config::SCREEN_SIZE.x as f32 - STAMINA_BAR_SIZE.0 - 12.0 };
+            self.draw_stamina_bar(dog, x, 60.0, target);
+        }
+        // Draw the time left!
+        let time_text = format_time(versus.time_left);
+        self.text.draw_text(&time_text, &self.font, [0.0, 0.0, 0.0],
+                            40, 362.0, 7.0, 200, &projection, target);
+        self.text.draw_text(&time_text, &self.font, [1.0, 1.0, 1.0],
+                            40, 360.0, 5.0, 200, &projection, target);
+
+        if world.game_state == GameState::VersusOver {
+            let winner = if scores[0] > scores[1] {
+                "Player 1 wins!"
+            } else if scores[1] > scores[0] {
+                "Player 2 wins!"
+            } else {
+                "It's a draw!"
+            };
+            let menu = &bindings.menu;
+            let text = format!("{} {} - {}\n{}: rematch  {}: next arena  {}: menu",
+                               winner, scores[0], scores[1],
+                               menu.restart.name(), menu.next_level.name(), menu.back.name());
+            self.text.draw_text(&text, &self.font, [0.0, 0.0, 0.0],
+                                40, 152.0, 502.0, 800, &projection, target);
+            self.text.draw_text(&text, &self.font, [1.0, 1.0, 1.0],
+                                40, 150.0, 500.0, 800, &projection, target);
+        }
+    }
+}
+
+/// Formats seconds as `m:ss.t`.
+fn format_time(time: f32) -> String {
+    let tenths = (time * 10.0) as u32;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+/// Colors cats on the minimap and off-screen arrows by what they're up to.
+fn cat_state_color(state: CatState) -> [f32; 3] {
+    match state {
+        CatState::Idle => [1.0, 1.0, 1.0],
+        CatState::Flee => [1.0, 0.9, 0.2],
+        CatState::InPen => [0.3, 0.9, 0.3],
+        CatState::Jittering => [1.0, 0.55, 0.1],
+        CatState::Cannonballing => [1.0, 0.15, 0.15],
+        CatState::Escaping => [0.8, 0.4, 1.0],
+    }
 }
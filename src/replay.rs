@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::InputState;
+
+// NOTE: Bump this whenever the file format or the simulation changes in a way that breaks old
+// replays. That includes new movement or scaring rules, the shipped levels and cats files, and
+// anything that draws from the world RNG more or less often.
+pub const REPLAY_VERSION: u32 = 14;
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub dt: f32,
+    pub game_speed: f32,
+    pub input: InputState,
+}
+
+/// Everything needed to play a session back exactly: the world's starting conditions and the
+/// input and timestep of every tick.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub start_level: u32,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, start_level: u32) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            start_level,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error on reading replay {}: {}", path.display(), e))?;
+        let replay: Replay = ron::de::from_str(&text)
+            .map_err(|e| format!("Error on parsing replay {}: {}", path.display(), e))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!("Replay {} has version {}, expected {}",
+                               path.display(), replay.version, REPLAY_VERSION));
+        }
+        Ok(replay)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = ron::ser::to_string(self)
+            .map_err(|e| format!("Error on serializing replay: {}", e))?;
+        fs::write(path, text)
+            .map_err(|e| format!("Error on writing replay {}: {}", path.display(), e))
+    }
+
+    pub fn record(&mut self, dt: f32, game_speed: f32, input: InputState) {
+        self.frames.push(ReplayFrame {
+            dt,
+            game_speed,
+            input,
+        });
+    }
+}
+
+/// Feeds a recorded replay back one tick at a time.
+pub struct ReplayPlayer {
+    replay: Replay,
+    next_frame: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            next_frame: 0,
+        }
+    }
+
+    pub fn next_frame(&mut self) -> Option<&ReplayFrame> {
+        let frame = self.replay.frames.get(self.next_frame);
+        if frame.is_some() {
+            self.next_frame += 1;
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::PlayerInput;
+    use crate::world::tests::{start, test_world};
+    use crate::world::GameWorld;
+
+    fn snapshot(world: &GameWorld) -> Vec<(f32, f32)> {
+        world.dogs.iter().map(|dog| (dog.pos.x, dog.pos.y))
+            .chain(world.cats.iter().map(|cat| (cat.pos.x, cat.pos.y)))
+            .collect()
+    }
+
+    #[test]
+    fn replay_reproduces_the_run() {
+        let seed = 1234;
+        let mut world = test_world(seed);
+        start(&mut world);
+        let mut replay = Replay::new(seed, 1);
+        for tick in 0..600 {
+            let mut input = InputState::default();
+            input.players[0] = PlayerInput {
+                move_x: ((tick / 40) % 3) as f32 - 1.0,
+                move_y: ((tick / 25) % 3) as f32 - 1.0,
+                woof: tick % 90 == 0,
+                sprint: tick % 200 < 50,
+            };
+            let dt = 0.01 + (tick % 7) as f32 * 0.002;
+            replay.record(dt, 1.0, input);
+            world.update(&input, dt);
+        }
+
+        let path = std::env::temp_dir().join("cat-chaser-replay-test.ron");
+        replay.save(&path).unwrap();
+        let mut player = ReplayPlayer::new(Replay::load(&path).unwrap());
+        let mut replayed = test_world(seed);
+        start(&mut replayed);
+        while let Some(frame) = player.next_frame() {
+            replayed.update(&frame.input, frame.dt);
+        }
+
+        assert_eq!(snapshot(&replayed), snapshot(&world));
+        assert!(replayed.game_state == world.game_state);
+    }
+}
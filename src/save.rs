@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+use crate::world::LevelResult;
+
+// NOTE: Bump this whenever the save format changes in a way old saves can't be read as.
+pub const SAVE_VERSION: u32 = 1;
+
+/// The best run of a single level.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct LevelRecord {
+    pub best_time: f32,
+    pub stars: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    /// The furthest level the player has reached, numbered from 1.
+    pub unlocked_level: u32,
+    /// Best runs by level number.
+    pub records: BTreeMap<u32, LevelRecord>,
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            unlocked_level: 1,
+            records: BTreeMap::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+impl SaveData {
+    /// Where the save file lives, under the user's data directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("cat-chaser").join("save.ron"))
+    }
+
+    /// Loads the save file, starting fresh if there isn't one. A save that can't be read is moved
+    /// aside to `save.ron.bak` rather than thrown away.
+    pub fn load() -> Self {
+        let path = match SaveData::path() {
+            Some(path) => path,
+            None => return SaveData::default(),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return SaveData::default(),
+        };
+
+        let error = match ron::de::from_str::<SaveData>(&text) {
+            Ok(ref save) if save.version != SAVE_VERSION => {
+                format!("Save {} has version {}, expected {}", path.display(), save.version, SAVE_VERSION)
+            }
+            Ok(save) => return save,
+            Err(e) => format!("Error on parsing save {}: {}", path.display(), e),
+        };
+        let backup = path.with_extension("ron.bak");
+        eprintln!("{}. Starting fresh and moving it to {}", error, backup.display());
+        if let Err(e) = fs::rename(&path, &backup) {
+            eprintln!("Error on backing up save {}: {}", path.display(), e);
+        }
+        SaveData::default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = SaveData::path().ok_or_else(|| "No data directory to save to".to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Error on creating save directory {}: {}", dir.display(), e))?;
+        }
+        let text = ron::ser::to_string(self)
+            .map_err(|e| format!("Error on serializing save: {}", e))?;
+        // Write to a temporary file first so a crash mid-write can't clobber the old save.
+        let temp_path = path.with_extension("ron.tmp");
+        fs::write(&temp_path, text)
+            .map_err(|e| format!("Error on writing save {}: {}", temp_path.display(), e))?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| format!("Error on writing save {}: {}", path.display(), e))
+    }
+
+    /// Records a completed level, keeping the best time and star rating and unlocking the next
+    /// level up to `max_level`.
+    pub fn record_result(&mut self, level_num: u32, result: LevelResult, max_level: u32) {
+        let record = self.records.entry(level_num).or_insert(LevelRecord {
+            best_time: result.time,
+            stars: result.stars,
+        });
+        record.best_time = record.best_time.min(result.time);
+        record.stars = record.stars.max(result.stars);
+
+        self.unlocked_level = self.unlocked_level.max((level_num + 1).min(max_level));
+    }
+}
@@ -0,0 +1,149 @@
+use midgar::{KeyCode, Midgar};
+use serde::{Deserialize, Serialize};
+
+const VOLUME_STEP: f32 = 0.1;
+const GAME_SPEED_STEP: f32 = 0.1;
+const MIN_GAME_SPEED: f32 = 0.5;
+const MAX_GAME_SPEED: f32 = 1.5;
+
+/// Player preferences. Missing fields fall back to their defaults so new settings don't break old
+/// saves.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub fullscreen: bool,
+    /// Scales how fast the game runs, for players who want things slower.
+    pub game_speed: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 1.0,
+            music_volume: 0.2,
+            sfx_volume: 1.0,
+            fullscreen: false,
+            game_speed: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SettingsOption {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Fullscreen,
+    GameSpeed,
+    Keys,
+    Done,
+}
+
+pub const SETTINGS_OPTIONS: [SettingsOption; 7] = [
+    SettingsOption::MasterVolume,
+    SettingsOption::MusicVolume,
+    SettingsOption::SfxVolume,
+    SettingsOption::Fullscreen,
+    SettingsOption::GameSpeed,
+    SettingsOption::Keys,
+    SettingsOption::Done,
+];
+
+impl Settings {
+    pub fn label(&self, option: SettingsOption) -> String {
+        let percent = |value: f32| (value * 100.0).round() as i32;
+        match option {
+            SettingsOption::MasterVolume => format!("Master volume: {}%", percent(self.master_volume)),
+            SettingsOption::MusicVolume => format!("Music volume: {}%", percent(self.music_volume)),
+            SettingsOption::SfxVolume => format!("Sound effects volume: {}%", percent(self.sfx_volume)),
+            SettingsOption::Fullscreen => format!("Window: {}", if self.fullscreen { "Fullscreen" } else { "Windowed" }),
+            SettingsOption::GameSpeed => format!("Game speed: {}%", percent(self.game_speed)),
+            SettingsOption::Keys => "Change keys".to_string(),
+            SettingsOption::Done => "Done".to_string(),
+        }
+    }
+
+    /// Nudges an option up or down a step. Returns whether anything changed.
+    fn adjust(&mut self, option: SettingsOption, steps: f32) -> bool {
+        let step = |value: f32, step: f32, min: f32, max: f32| {
+            // Round so repeated steps don't drift away from nice numbers.
+            let value = ((value + step * steps) / step).round() * step;
+            value.max(min).min(max)
+        };
+        let old = self.clone();
+        match option {
+            SettingsOption::MasterVolume => self.master_volume = step(self.master_volume, VOLUME_STEP, 0.0, 1.0),
+            SettingsOption::MusicVolume => self.music_volume = step(self.music_volume, VOLUME_STEP, 0.0, 1.0),
+            SettingsOption::SfxVolume => self.sfx_volume = step(self.sfx_volume, VOLUME_STEP, 0.0, 1.0),
+            SettingsOption::Fullscreen => self.fullscreen = !self.fullscreen,
+            SettingsOption::GameSpeed => {
+                self.game_speed = step(self.game_speed, GAME_SPEED_STEP, MIN_GAME_SPEED, MAX_GAME_SPEED);
+            }
+            SettingsOption::Keys | SettingsOption::Done => return false,
+        }
+        *self != old
+    }
+}
+
+/// What the settings screen wants the app to do after an update.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SettingsEvent {
+    Nothing,
+    /// A setting changed and should be applied right away.
+    Changed,
+    OpenKeys,
+    Done,
+}
+
+/// The settings screen. Like the rebinding screen, it always navigates with the arrows, Enter and
+/// Escape.
+pub struct SettingsMenu {
+    pub settings: Settings,
+    pub selected: usize,
+}
+
+impl SettingsMenu {
+    pub fn new(settings: Settings) -> Self {
+        SettingsMenu {
+            settings,
+            selected: 0,
+        }
+    }
+
+    pub fn update(&mut self, midgar: &Midgar) -> SettingsEvent {
+        let input = midgar.input();
+        let option = SETTINGS_OPTIONS[self.selected];
+
+        let steps = if input.was_key_pressed(KeyCode::Left) {
+            -1.0
+        } else if input.was_key_pressed(KeyCode::Right) ||
+            (option == SettingsOption::Fullscreen && input.was_key_pressed(KeyCode::Return)) {
+            1.0
+        } else {
+            0.0
+        };
+        if steps != 0.0 && self.settings.adjust(option, steps) {
+            return SettingsEvent::Changed;
+        }
+
+        if input.was_key_pressed(KeyCode::Escape) {
+            SettingsEvent::Done
+        } else if input.was_key_pressed(KeyCode::Return) {
+            match option {
+                SettingsOption::Keys => SettingsEvent::OpenKeys,
+                SettingsOption::Done => SettingsEvent::Done,
+                _ => SettingsEvent::Nothing,
+            }
+        } else {
+            if input.was_key_pressed(KeyCode::Up) {
+                self.selected = (self.selected + SETTINGS_OPTIONS.len() - 1) % SETTINGS_OPTIONS.len();
+            } else if input.was_key_pressed(KeyCode::Down) {
+                self.selected = (self.selected + 1) % SETTINGS_OPTIONS.len();
+            }
+            SettingsEvent::Nothing
+        }
+    }
+}
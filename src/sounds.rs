@@ -1,51 +1,237 @@
-pub use ears::{Sound, AudioController, Music};
-use rand::distributions::{Distribution, Uniform};
+#[cfg(feature = "audio")]
+use std::cell::RefCell;
+#[cfg(feature = "audio")]
+use std::collections::HashMap;
+#[cfg(feature = "audio")]
+use std::rc::Rc;
 
+#[cfg(feature = "audio")]
+use ears::{listener, AudioController, Music, Sound, SoundData};
+
+pub const DOG_WOOF: &str = "assets/sounds/dog_woof_1.wav";
+pub const DOG_YIP: &str = "assets/sounds/dog_yip_1.wav";
+
+// How many sound effects can play at once. Past this, voices take turns getting cut off.
+#[cfg(feature = "audio")]
+const MAX_VOICES: usize = 16;
+// Sounds this far from the nearest dog, in game units, are as quiet as they get.
+#[cfg(feature = "audio")]
+const HEARING_DISTANCE: f32 = 600.0;
+// How loud the furthest sounds still are, so faraway cats don't go completely silent.
+#[cfg(feature = "audio")]
+const MIN_DISTANCE_GAIN: f32 = 0.25;
+
+/// Plays the game's music and sound effects.
+pub trait AudioBackend {
+    /// Scales every sound and track the game plays.
+    fn set_master_volume(&mut self, volume: f32);
+    fn set_music_volume(&mut self, volume: f32);
+    fn set_sfx_volume(&mut self, volume: f32);
+    /// Loads a clip ahead of time so playing it later doesn't hitch.
+    fn preload(&mut self, path: &str);
+    /// Plays a clip. `pan` goes from -1.0 for hard left to 1.0 for hard right, and `distance` is
+    /// how far the sound is from the nearest dog.
+    fn play(&mut self, path: &str, pan: f32, distance: f32);
+    /// Starts the music from the intro.
+    fn start_music(&mut self);
+    /// Keeps the music going. Call once a frame.
+    fn update(&mut self);
+}
+
+/// Audio that plays nothing, for builds without the `audio` feature and machines without a sound
+/// device.
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn set_master_volume(&mut self, _volume: f32) {}
+    fn set_music_volume(&mut self, _volume: f32) {}
+    fn set_sfx_volume(&mut self, _volume: f32) {}
+    fn preload(&mut self, _path: &str) {}
+    fn play(&mut self, _path: &str, _pan: f32, _distance: f32) {}
+    fn start_music(&mut self) {}
+    fn update(&mut self) {}
+}
+
+/// The best audio this build and machine can do, falling back to silence if sound doesn't work.
+#[cfg(feature = "audio")]
+pub fn new_audio() -> Box<dyn AudioBackend> {
+    match Sounds::new() {
+        Ok(sounds) => Box::new(sounds),
+        Err(e) => {
+            eprintln!("{}. Playing without sound.", e);
+            Box::new(NullAudio)
+        }
+    }
+}
+
+/// Always `NullAudio`, since this build has no sound.
+#[cfg(not(feature = "audio"))]
+pub fn new_audio() -> Box<dyn AudioBackend> {
+    Box::new(NullAudio)
+}
+
+/// A sound effect playing, or that played last, on one voice.
+#[cfg(feature = "audio")]
+struct Voice {
+    clip: String,
+    sound: Sound,
+    // How much quieter distance makes the sound, on top of the effects volume.
+    gain: f32,
+}
+
+/// Audio through OpenAL. Each clip is loaded once and shared by every voice that plays it.
+#[cfg(feature = "audio")]
 pub struct Sounds {
     pub intro_music: Music,
     pub background_music: Music,
+    // Clips that failed to load are kept as None so they're only reported once.
+    clips: HashMap<String, Option<Rc<RefCell<SoundData>>>>,
+    voices: Vec<Voice>,
+    // The voice to cut off next when they're all busy.
+    next_steal: usize,
+    sfx_volume: f32,
 }
 
+#[cfg(feature = "audio")]
 impl Sounds {
-    pub fn new() -> Self {
-        let mut background_music = Music::new("assets/sounds/trolling_doggo_loop.wav").expect("Error on loading trolling_doggo_loop.");
+    pub fn new() -> Result<Self, String> {
+        let intro_music = Music::new("assets/sounds/trolling_doggo.wav")
+            .map_err(|e| format!("Error on loading trolling_doggo: {}", e))?;
+        let mut background_music = Music::new("assets/sounds/trolling_doggo_loop.wav")
+            .map_err(|e| format!("Error on loading trolling_doggo_loop: {}", e))?;
         background_music.set_looping(true);
-        Sounds {
-            intro_music: Music::new("assets/sounds/trolling_doggo.wav").expect("Error on loading trolling_doggo."),
+        let mut sounds = Sounds {
+            intro_music,
             background_music,
+            clips: HashMap::new(),
+            voices: Vec::new(),
+            next_steal: 0,
+            sfx_volume: 1.0,
+        };
+        sounds.preload(DOG_WOOF);
+        sounds.preload(DOG_YIP);
+        Ok(sounds)
+    }
+
+    fn clip(&mut self, path: &str) -> Option<Rc<RefCell<SoundData>>> {
+        if let Some(data) = self.clips.get(path) {
+            return data.clone();
         }
+        let data = match SoundData::new(path) {
+            Ok(data) => Some(Rc::new(RefCell::new(data))),
+            Err(e) => {
+                eprintln!("Error on loading {}: {}", path, e);
+                None
+            }
+        };
+        self.clips.insert(path.to_string(), data.clone());
+        data
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioBackend for Sounds {
+    fn set_master_volume(&mut self, volume: f32) {
+        listener::set_volume(volume);
     }
 
-    pub fn angry_meow() -> Sound {
-        let mut rng = rand::thread_rng();
-        let range = Uniform::new(1, 4);
-        let i = range.sample(&mut rng);
-        match i {
-            1 => Sound::new("assets/sounds/angry_cat_meow_1.wav").expect("Error on loading angry_meow_1."),
-            2 => Sound::new("assets/sounds/angry_cat_meow_2.wav").expect("Error on loading angry_meow_2."),
-            3 => Sound::new("assets/sounds/angry_cat_meow_3.wav").expect("Error on loading angry_meow_3."),
-            4 => Sound::new("assets/sounds/angry_cat_meow_4.wav").expect("Error on loading angry_meow_4."),
-            _ => Sound::new("assets/sounds/angry_cat_meow_1.wav").expect("Error on loading angry_meow_1."),
+    fn set_music_volume(&mut self, volume: f32) {
+        self.intro_music.set_volume(volume);
+        self.background_music.set_volume(volume);
+    }
+
+    fn set_sfx_volume(&mut self, volume: f32) {
+        self.sfx_volume = volume;
+        for voice in &mut self.voices {
+            voice.sound.set_volume(volume * voice.gain);
         }
     }
 
-    pub fn basic_meow() -> Sound {
-        Sound::new("assets/sounds/basic_cat_meow_1.wav").expect("Error on loading basic_meow_1.")
+    fn preload(&mut self, path: &str) {
+        self.clip(path);
     }
 
-    pub fn kitten_meow() -> Sound {
-        Sound::new("assets/sounds/kitten_meow_1.wav").expect("Error on loading kitten_meow_1.")
+    /// Plays a clip on a free voice. If every voice is busy, cuts one off, going round them in turn.
+    fn play(&mut self, path: &str, pan: f32, distance: f32) {
+        let free_voice = self.voices.iter()
+            .position(|voice| voice.clip == path && !voice.sound.is_playing());
+        let index = match free_voice {
+            Some(index) => index,
+            None => {
+                let data = match self.clip(path) {
+                    Some(data) => data,
+                    None => return,
+                };
+                let mut sound = match Sound::new_with_data(data) {
+                    Ok(sound) => sound,
+                    Err(e) => {
+                        eprintln!("Error on playing {}: {}", path, e);
+                        return;
+                    }
+                };
+                // Voices are placed relative to the listener, so panning doesn't depend on where
+                // the listener is.
+                sound.set_relative(true);
+                let voice = Voice {
+                    clip: path.to_string(),
+                    sound,
+                    gain: 1.0,
+                };
+
+                if self.voices.len() < MAX_VOICES {
+                    self.voices.push(voice);
+                    self.voices.len() - 1
+                } else {
+                    let index = match self.voices.iter().position(|voice| !voice.sound.is_playing()) {
+                        Some(index) => index,
+                        None => {
+                            let index = self.next_steal;
+                            self.next_steal = (index + 1) % MAX_VOICES;
+                            self.voices[index].sound.stop();
+                            index
+                        }
+                    };
+                    self.voices[index] = voice;
+                    index
+                }
+            }
+        };
+        let voice = &mut self.voices[index];
+        let pan = pan.max(-1.0).min(1.0);
+        // Keep the voice one unit from the listener so OpenAL pans it without making it quieter.
+        voice.sound.set_position([pan, 0.0, -(1.0 - pan * pan).sqrt()]);
+        let falloff = (distance / HEARING_DISTANCE).min(1.0);
+        voice.gain = 1.0 - falloff * (1.0 - MIN_DISTANCE_GAIN);
+        voice.sound.set_volume(self.sfx_volume * voice.gain);
+        voice.sound.play();
     }
 
-    pub fn fat_meow() -> Sound {
-        Sound::new("assets/sounds/fat_cat_meow_1.wav").expect("Error on loading fat_meow_1.")
+    fn start_music(&mut self) {
+        self.intro_music.play();
     }
 
-    pub fn dog_yip() -> Sound {
-        Sound::new("assets/sounds/dog_yip_1.wav").expect("Error on loading dog_yip_1.")
+    fn update(&mut self) {
+        // Loop the background music once the intro is done.
+        if !self.intro_music.is_playing() && !self.background_music.is_playing() {
+            self.background_music.play();
+        }
     }
+}
+
+// Without the audio feature there's no sound device to open, so these run anywhere.
+#[cfg(all(test, not(feature = "audio")))]
+mod tests {
+    use super::*;
 
-    pub fn dog_woof() -> Sound {
-        Sound::new("assets/sounds/dog_woof_1.wav").expect("Error on loading dog_woof_1.")
+    #[test]
+    fn silent_build_plays_without_a_device() {
+        let mut audio = new_audio();
+        audio.set_master_volume(0.5);
+        audio.set_sfx_volume(0.5);
+        audio.preload(DOG_WOOF);
+        audio.start_music();
+        audio.play(DOG_WOOF, -1.0, 100.0);
+        audio.play("assets/sounds/missing.wav", 2.0, 0.0);
+        audio.update();
     }
 }
@@ -1,13 +1,16 @@
-use midgar::Midgar;
-
-use cgmath::{self, InnerSpace, Vector2, Zero};
-use midgar::KeyCode;
+use cgmath::{self, InnerSpace, MetricSpace, Vector2, Zero};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
 use crate::entities::*;
-use crate::sounds::Sounds;
-use crate::level::{Level, MAX_LEVEL};
+use crate::input::{InputState, MAX_PLAYERS};
+use crate::level::{Level, LevelDef};
 use crate::party::Party;
 
 const MOVE_SPEED: f32 = 150.0;
+// How long versus matches last in arenas that don't say.
+const VERSUS_TIME_LIMIT: f32 = 60.0;
+// Penned cats with a dog this close to their box stay put.
+const PEN_WATCH_RADIUS: f32 = 200.0;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum GameState {
@@ -17,192 +20,522 @@ pub enum GameState {
     Running,
     Won,
     GameOver,
+    Versus,
+    VersusOver,
+    Paused,
+    /// A dog ran out of health, and the level has to be retried.
+    Failed,
+}
+
+/// Things the world needs the app to do for it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AppRequest {
+    OpenSettings,
+    Quit,
+}
+
+/// Sounds the world wants played. Dogs and cats are referred to by their index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoundEvent {
+    Woof(usize),
+    Yip(usize),
+    Meow {
+        cat: usize,
+        angry: bool,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PauseOption {
+    Resume,
+    RestartLevel,
+    Settings,
+    MainMenu,
+    Quit,
+}
+
+pub const PAUSE_OPTIONS: [PauseOption; 5] = [
+    PauseOption::Resume,
+    PauseOption::RestartLevel,
+    PauseOption::Settings,
+    PauseOption::MainMenu,
+    PauseOption::Quit,
+];
+
+pub struct PauseMenu {
+    pub selected: usize,
+    /// The state to go back to when resuming.
+    pub paused_state: GameState,
+}
+
+#[derive(Clone, Copy)]
+pub struct LevelResult {
+    pub time: f32,
+    pub stars: u32,
+}
+
+/// A versus match in progress. Each player herds cats into the box they own.
+pub struct VersusMatch {
+    /// Which arena is being played, numbered from 1 like levels.
+    pub arena: u32,
+    pub time_left: f32,
+    // The campaign level to go back to when the match is over.
+    campaign_level: u32,
 }
 
 pub struct GameWorld {
     pub game_state: GameState,
+    pub levels: Vec<LevelDef>,
+    pub arenas: Vec<LevelDef>,
+    pub archetypes: Vec<CatArchetype>,
     pub level: Level,
-    pub dog: Dog,
+    pub dogs: Vec<Dog>,
     pub cats: Vec<Cat>,
-    pub cats_scored: u32,
+
+    /// Seconds spent on the level, in real time. Slowing the game down doesn't make par times
+    /// any easier.
+    pub level_time: f32,
+    /// How fast the game runs compared to real time, from the settings.
+    pub game_speed: f32,
+    pub result: Option<LevelResult>,
+    pub versus: Option<VersusMatch>,
+    pub pause_menu: Option<PauseMenu>,
+    pub app_request: Option<AppRequest>,
+    /// Sounds to play for the latest tick.
+    pub sound_events: Vec<SoundEvent>,
 
     pub the_party: Party,
+
+    rng: XorShiftRng,
 }
 
 impl GameWorld {
-    pub fn new() -> Self {
-        let level = Level::new(1);
-        let mut yip_sound = Sounds::dog_yip();
-//        yip_sound.set_volume(3.0);
-        let dog = Dog {
-            pos: level.cat_box.pos,
-            vel: Vector2::zero(),
-            size: cgmath::Vector2::new(30.0, 30.0),
-            facing: Facing::Left,
-            left_key: KeyCode::Left,
-            right_key: KeyCode::Right,
-            up_key: KeyCode::Up,
-            down_key: KeyCode::Down,
-            dog_state: DogState::Chasing,
-            hit_time: 0.0,
-            hit_frame: 0,
-            yip_sound,
-            woof_sound: Sounds::dog_woof(),
-        };
-        let cats = level.generate_cats();
+    pub fn new(seed: u64, levels: Vec<LevelDef>, arenas: Vec<LevelDef>, archetypes: Vec<CatArchetype>,
+               start_level: u32) -> Self {
+        // All gameplay randomness comes from this, so a seed always plays out the same way.
+        let mut rng = XorShiftRng::seed_from_u64(seed);
+        let mut level = Level::new(&levels, start_level);
+        let dog = Dog::new(0, dog_start_pos(&level, 0));
+        let cats = level.generate_cats(&archetypes, &mut rng);
+        level.update_quotas(&cats);
+        let the_party = Party::new(&mut rng);
 
         GameWorld {
             game_state: GameState::StartMenu,
+            levels,
+            arenas,
+            archetypes,
             level,
-            dog,
+            dogs: vec![dog],
             cats,
-            cats_scored: 0,
-            the_party: Party::new(),
+            level_time: 0.0,
+            game_speed: 1.0,
+            result: None,
+            versus: None,
+            pause_menu: None,
+            app_request: None,
+            sound_events: Vec::new(),
+            the_party,
+            rng,
         }
     }
 
-    pub fn update(&mut self, midgar: &Midgar, dt: f32) {
+    pub fn update(&mut self, input: &InputState, dt: f32) {
+        self.sound_events.clear();
+
+        // The next player can join in by woofing. That woof only joins, it doesn't bark.
+        let mut input = *input;
+        let player = self.dogs.len();
+        if player < MAX_PLAYERS && input.players[player].woof {
+            self.dogs.push(Dog::new(player, dog_start_pos(&self.level, player)));
+            input.players[player].woof = false;
+        }
+        let input = &input;
+
         match self.game_state {
-            GameState::StartMenu => self.update_start_menu(midgar, dt),
-            GameState::Credits => self.update_credits(midgar, dt),
-            GameState::HowToPlay => self.update_how_to_play(midgar, dt),
-            GameState::Running => self.update_running(midgar, dt),
-            GameState::Won => self.update_won(midgar, dt),
-            GameState::GameOver => self.update_game_over(midgar, dt),
+            GameState::StartMenu => self.update_start_menu(input, dt),
+            GameState::Credits => self.update_credits(input, dt),
+            GameState::HowToPlay => self.update_how_to_play(input, dt),
+            GameState::Running => self.update_running(input, dt),
+            GameState::Won => self.update_won(input, dt),
+            GameState::GameOver => self.update_game_over(input, dt),
+            GameState::Versus => self.update_versus(input, dt),
+            GameState::VersusOver => self.update_versus_over(input, dt),
+            GameState::Paused => self.update_paused(input, dt),
+            GameState::Failed => self.update_failed(input, dt),
         }
     }
 
     fn restart(&mut self) {
-        self.dog.pos = self.level.cat_box.pos;
-        let cats = self.level.generate_cats();
+        for dog in &mut self.dogs {
+            dog.respawn(dog_start_pos(&self.level, dog.player));
+        }
+        for cat_box in &mut self.level.cat_boxes {
+            cat_box.reset();
+        }
+        let cats = self.level.generate_cats(&self.archetypes, &mut self.rng);
+        self.level.update_quotas(&cats);
         self.cats = cats;
-        self.game_state = GameState::Running;
+        self.level_time = 0.0;
+        self.result = None;
+        self.game_state = match self.versus {
+            Some(ref mut versus) => {
+                versus.time_left = self.level.time_limit.unwrap_or(VERSUS_TIME_LIMIT);
+                GameState::Versus
+            }
+            None => GameState::Running,
+        };
+    }
+
+    pub fn max_level(&self) -> u32 {
+        self.levels.len() as u32
     }
 
     fn next_level(&mut self) {
-        if self.level.level_num >= MAX_LEVEL {
+        if self.level.level_num >= self.max_level() {
             self.game_state = GameState::GameOver;
             return;
         }
 
-        self.level.next_level();
+        self.level.next_level(&self.levels);
+        self.restart();
+    }
+
+    /// Starts a versus match in the given arena, bringing in a second dog if nobody has joined.
+    fn start_versus(&mut self, arena: u32) {
+        while self.dogs.len() < MAX_PLAYERS {
+            let player = self.dogs.len();
+            self.dogs.push(Dog::new(player, Vector2::zero()));
+        }
+        let campaign_level = match self.versus {
+            Some(ref versus) => versus.campaign_level,
+            None => self.level.level_num,
+        };
+        self.level = Level::new(&self.arenas, arena);
+        self.versus = Some(VersusMatch {
+            arena: self.level.level_num,
+            time_left: 0.0,
+            campaign_level,
+        });
         self.restart();
     }
 
-    fn update_start_menu(&mut self, midgar: &Midgar, _dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::Return) {
+    fn quit_versus(&mut self) {
+        if let Some(versus) = self.versus.take() {
+            self.level = Level::new(&self.levels, versus.campaign_level);
+            self.restart();
+        }
+        self.game_state = GameState::StartMenu;
+    }
+
+    /// What the camera should follow: the middle of all the dogs.
+    pub fn camera_focus(&self) -> Vector2<f32> {
+        let sum = self.dogs.iter().fold(Vector2::zero(), |sum, dog| sum + dog.pos);
+        sum / self.dogs.len() as f32
+    }
+
+    /// How many cats each player has in their box.
+    pub fn versus_scores(&self) -> [u32; MAX_PLAYERS] {
+        let mut scores = [0; MAX_PLAYERS];
+        for cat_box in &self.level.cat_boxes {
+            if let Some(owner) = cat_box.owner {
+                scores[owner] += cat_box.scored;
+            }
+        }
+        scores
+    }
+
+    /// Whether the game can be paused right now.
+    pub fn can_pause(&self) -> bool {
+        match self.game_state {
+            GameState::Running | GameState::Won | GameState::Versus | GameState::VersusOver => true,
+            _ => false,
+        }
+    }
+
+    fn pause(&mut self) {
+        self.pause_menu = Some(PauseMenu {
+            selected: 0,
+            paused_state: self.game_state,
+        });
+        self.game_state = GameState::Paused;
+    }
+
+    fn update_paused(&mut self, input: &InputState, _dt: f32) {
+        let menu = match self.pause_menu {
+            Some(ref mut menu) => menu,
+            None => return,
+        };
+        if input.menu_up {
+            menu.selected = (menu.selected + PAUSE_OPTIONS.len() - 1) % PAUSE_OPTIONS.len();
+        } else if input.menu_down {
+            menu.selected = (menu.selected + 1) % PAUSE_OPTIONS.len();
+        }
+
+        let option = if input.pause || input.back {
+            PauseOption::Resume
+        } else if input.confirm {
+            PAUSE_OPTIONS[menu.selected]
+        } else {
+            return;
+        };
+        let paused_state = menu.paused_state;
+        match option {
+            PauseOption::Resume => {
+                self.game_state = paused_state;
+                self.pause_menu = None;
+            }
+            PauseOption::RestartLevel => {
+                self.pause_menu = None;
+                self.restart();
+            }
+            PauseOption::Settings => self.app_request = Some(AppRequest::OpenSettings),
+            PauseOption::MainMenu => {
+                self.pause_menu = None;
+                if self.versus.is_some() {
+                    self.quit_versus();
+                } else {
+                    self.restart();
+                    self.game_state = GameState::StartMenu;
+                }
+            }
+            PauseOption::Quit => self.app_request = Some(AppRequest::Quit),
+        }
+    }
+
+    fn update_start_menu(&mut self, input: &InputState, _dt: f32) {
+        if input.quit {
+            self.app_request = Some(AppRequest::Quit);
+        } else if input.confirm {
             self.game_state = GameState::HowToPlay;
-        } else if midgar.input().was_key_pressed(KeyCode::Tab) {
+        } else if input.versus {
+            self.start_versus(1);
+        } else if input.back {
             self.game_state = GameState::Credits;
         }
     }
 
-    fn update_credits(&mut self, midgar: &Midgar, _dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::Return) || midgar.input().was_key_pressed(KeyCode::Tab) {
+    fn update_credits(&mut self, input: &InputState, _dt: f32) {
+        if input.confirm || input.back {
             self.game_state = GameState::StartMenu;
         }
     }
 
-    fn update_how_to_play(&mut self, midgar: &Midgar, _dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::Return) {
+    fn update_how_to_play(&mut self, input: &InputState, _dt: f32) {
+        if input.confirm {
             self.game_state = GameState::Running;
         }
     }
 
-    fn update_game_over(&mut self, midgar: &Midgar, dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::R) {
-            self.level = Level::new(1);
+    fn update_game_over(&mut self, input: &InputState, dt: f32) {
+        if input.restart {
+            self.level = Level::new(&self.levels, 1);
             self.restart();
             return;
         }
 
-        self.the_party.update(dt);
+        self.the_party.update(dt, &mut self.rng);
     }
 
-    fn update_won(&mut self, midgar: &Midgar, dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::N) {
+    fn update_won(&mut self, input: &InputState, dt: f32) {
+        if input.next_level {
             self.next_level();
         }
 
-        self.update_running(midgar, dt);
+        self.update_running(input, dt);
     }
 
-    fn update_running(&mut self, midgar: &Midgar, dt: f32) {
-        if midgar.input().was_key_pressed(KeyCode::R) {
+    fn update_running(&mut self, input: &InputState, dt: f32) {
+        if input.pause {
+            self.pause();
+            return;
+        }
+        if input.restart {
             self.restart();
             return;
         }
-        if midgar.input().was_key_pressed(KeyCode::Tab) {
+        if input.skip_level {
             self.next_level();
             return;
         }
-        if midgar.input().was_key_pressed(KeyCode::Space) {
-            self.dog.woof();
+
+        self.simulate(input, dt);
+
+        // Dogs only get worn down in the campaign. Versus is about the cats.
+        if self.game_state == GameState::Running && self.dogs.iter().any(|dog| dog.health == 0) {
+            self.game_state = GameState::Failed;
+            return;
         }
 
-        // TODO: consider moving this into a poll input method
-        // TODO: Clamp dog to level bounds.
-        let mut dir = Vector2::zero();
-        if midgar.input().is_key_held(self.dog.left_key) && !midgar.input().is_key_held(self.dog.right_key) {
-            dir.x -= 1.0;
+        if self.game_state != GameState::Won {
+            self.level_time += dt / self.game_speed;
+
+            // Check win condition!
+            if self.level.is_complete() {
+                self.result = Some(LevelResult {
+                    time: self.level_time,
+                    stars: self.level.stars_for(self.level_time),
+                });
+                self.game_state = if self.level.level_num < self.max_level() {
+                    GameState::Won
+                } else {
+                    GameState::GameOver
+                };
+            }
         }
-        if midgar.input().is_key_held(self.dog.right_key) && !midgar.input().is_key_held(self.dog.left_key) {
-            dir.x += 1.0;
+    }
+
+    fn update_failed(&mut self, input: &InputState, _dt: f32) {
+        if input.restart || input.confirm {
+            self.restart();
+        } else if input.skip_level {
+            self.next_level();
         }
-        if midgar.input().is_key_held(self.dog.up_key) && !midgar.input().is_key_held(self.dog.down_key) {
-            dir.y -= 1.0;
+    }
+
+    fn update_versus(&mut self, input: &InputState, dt: f32) {
+        if input.pause {
+            self.pause();
+            return;
         }
-        if midgar.input().is_key_held(self.dog.down_key) && !midgar.input().is_key_held(self.dog.up_key) {
-            dir.y += 1.0;
+        if input.restart {
+            self.restart();
+            return;
         }
-        if !dir.is_zero() {
-            dir = dir.normalize();
+        if input.back {
+            self.quit_versus();
+            return;
+        }
+
+        self.simulate(input, dt);
+
+        // The match ends when time runs out or there are no cats left to fight over.
+        let all_penned = self.cats.iter().all(|cat| cat.pen.is_some());
+        if let Some(ref mut versus) = self.versus {
+            versus.time_left = (versus.time_left - dt).max(0.0);
+            if versus.time_left <= 0.0 || all_penned {
+                self.game_state = GameState::VersusOver;
+            }
         }
-        if dir.x != 0.0 {
-            self.dog.facing = if dir.x > 0.0 {
-                Facing::Right
-            } else {
-                Facing::Left
-            };
+    }
+
+    fn update_versus_over(&mut self, input: &InputState, _dt: f32) {
+        if input.pause {
+            self.pause();
+        } else if input.restart {
+            self.restart();
+        } else if input.next_level {
+            // Move on to the next arena, wrapping back around to the first.
+            let arena = self.versus.as_ref().map_or(1, |versus| versus.arena % self.arenas.len() as u32 + 1);
+            self.start_versus(arena);
+        } else if input.back {
+            self.quit_versus();
         }
-        self.dog.vel = dir * MOVE_SPEED;
-        let delta_pos = self.dog.vel * dt;
-        self.dog.try_move(&self.level.bounds, delta_pos);
+    }
 
-        self.dog.update(dt);
+    /// Moves the dogs, boxes and cats.
+    fn simulate(&mut self, input: &InputState, dt: f32) {
+        let mut woofs = Vec::new();
+        for dog in &mut self.dogs {
+            let player_input = &input.players[dog.player];
+            if player_input.woof && dog.try_woof() {
+                self.sound_events.push(SoundEvent::Woof(dog.player));
+                woofs.push((dog.player, dog.pos));
+            }
 
-        let mut cats_scored = 0;
-        // Cats move or run!
+            // Sticks pushed part way move the dog slower, but diagonals shouldn't be faster.
+            let mut dir = player_input.move_dir();
+            if dir.magnitude() > 1.0 {
+                dir = dir.normalize();
+            }
+            if dir.x != 0.0 {
+                dog.facing = if dir.x > 0.0 {
+                    Facing::Right
+                } else {
+                    Facing::Left
+                };
+            }
+            dog.update_stamina(player_input.sprint && !dir.is_zero(), dt);
+            dog.vel = dir * MOVE_SPEED;
+            if dog.sprinting {
+                dog.vel *= SPRINT_SCALAR;
+            }
+            let delta_pos = dog.vel * dt;
+            dog.try_move(&self.level.bounds, &self.level.obstacles, delta_pos);
+            dog.apply_knockback(&self.level.bounds, &self.level.obstacles, dt);
+
+            dog.update(dt);
+        }
+
+        // Move the boxes, and any cats in them along with them.
+        let box_moves: Vec<_> = self.level.cat_boxes.iter_mut()
+            .map(|cat_box| {
+                cat_box.scored = 0;
+                cat_box.update(dt)
+            })
+            .collect();
         for cat in &mut self.cats {
+            if let Some(pen) = cat.pen {
+                cat.pos += box_moves[pen];
+            }
+        }
+
+        // Cats move or run!
+        for (i, cat) in self.cats.iter_mut().enumerate() {
             let prev_state = cat.state.clone();
-            match cat.update_state(&self.dog, &self.level.cat_box) {
-                CatState::Idle => { cat.idle(&self.level.bounds, &self.level.cat_box, dt) },
+            let pen = self.level.pen_for(cat);
+            // Barks scare off cats that are out loose, and wind up penned ones.
+            for &(player, pos) in &woofs {
+                if cat.pos.distance(pos) < WOOF_RADIUS {
+                    if pen.is_none() {
+                        cat.scare(pos, player);
+                    } else {
+                        cat.pester(player);
+                    }
+                }
+            }
+            match cat.update_state(&self.dogs, pen) {
+                CatState::Idle => { cat.idle(&self.level.bounds, &self.level.obstacles, &self.level.cat_boxes, dt, &mut self.rng) },
                 CatState::InPen => {
-                    cat.in_pen(&self.level.bounds, dt);
-                    cats_scored += 1;
+                    if let Some(pen) = cat.pen {
+                        let cat_box = &self.level.cat_boxes[pen];
+                        let watched = self.dogs.iter().any(|dog| dog.pos.distance(cat_box.pos) < PEN_WATCH_RADIUS);
+                        cat.in_pen(cat_box, watched, dt, &mut self.rng);
+                    }
+                    // The cat might have just escaped.
+                    if let Some(pen) = cat.pen {
+                        self.level.cat_boxes[pen].scored += 1;
+                    }
+                },
+                CatState::Escaping => {
+                    cat.escape(&self.level.bounds, &self.level.obstacles, dt)
                 },
                 CatState::Flee => {
-                    let dir = &cat.pos - self.dog.pos;
-                    cat.flee(&self.level.bounds, &dir, dt)
+                    let dog = &self.dogs[cat.annoyed_by];
+                    let dir = &cat.pos - dog.pos;
+                    cat.flee(&self.level.bounds, &self.level.obstacles, &dir, dog.annoyance_scalar(), dt)
                 },
                 CatState::Jittering => {
-                    cat.jitter(dt, &self.dog)
+                    cat.jitter(dt, &self.dogs, &mut self.rng)
                 }
                 CatState::Cannonballing => {
-                    cat.cannonball(&self.level.bounds, dt, &mut self.dog)
+                    cat.cannonball(&self.level.bounds, &self.level.obstacles, dt, &mut self.dogs, &mut self.sound_events)
                 }
             }
+            cat.apply_impulse(&self.level.bounds, &self.level.obstacles, dt);
 
             if cat.state == CatState::Idle || cat.state == CatState::InPen || cat.state == CatState::Flee {
                 // Basic meow
                 if cat.meow_time >= cat.meow_interval {
-                    cat.meow();
+                    let angry = cat.meow();
+                    self.sound_events.push(SoundEvent::Meow { cat: i, angry });
                 }
                 cat.meow_time += dt;
             } else if prev_state != cat.state {
                 // Angry meow
                 if cat.state == CatState::Jittering || cat.state == CatState::Cannonballing {
-                    cat.meow();
+                    let angry = cat.meow();
+                    self.sound_events.push(SoundEvent::Meow { cat: i, angry });
                 }
             }
 
@@ -214,20 +547,234 @@ impl GameWorld {
                 };
             }
         }
+    }
+}
 
-        self.cats_scored = cats_scored;
+/// Where each player's dog starts out: at their own box in versus mode, otherwise side by side.
+fn dog_start_pos(level: &Level, player: usize) -> Vector2<f32> {
+    match level.box_for_player(player) {
+        Some(index) => level.cat_boxes[index].start_pos,
+        None => level.start_pos() + cgmath::vec2(player as f32 * 40.0, 0.0),
+    }
+}
 
-        if self.game_state != GameState::Won {
-            // Check win condition!
-            if self.cats_scored == self.level.num_cats && self.level.level_num < MAX_LEVEL {
-                self.game_state = GameState::Won;
-            } else if self.cats_scored == self.level.num_cats && self.level.level_num >= MAX_LEVEL {
-                self.game_state = GameState::GameOver;
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use gilrs::Button;
+
+    use crate::input::{GamepadSource, PadEvent, PlayerInput};
+
+    // Two small levels, so finishing the first one is a win and not the end of the game.
+    const LEVELS: &str = r#"[
+        (bounds: (400, 300), cat_boxes: [(pos: (200.0, 150.0), size: (60.0, 60.0))], cats: {"basic": 2}),
+        (bounds: (400, 300), cat_boxes: [(pos: (100.0, 100.0), size: (60.0, 60.0))], cats: {"basic": 3}),
+    ]"#;
+    const CATS: &str = r#"[(
+        name: "basic",
+        animations: Basic,
+        sounds: (meow: "meow.wav", angry: ["angry.wav"]),
+        speed: 150.0,
+        flee_radius: 70.0,
+        flee_scalar: 1.0,
+        annoyance_rate: 1.0,
+        calming_rate: 0.75,
+        rw_radius: 9.0,
+        meow_interval: 3.0,
+        meow_start: (-3.0, 2.0),
+    )]"#;
+
+    pub fn test_archetypes() -> Vec<CatArchetype> {
+        ron::de::from_str(CATS).expect("Error on parsing test cats.")
+    }
+
+    pub fn test_world(seed: u64) -> GameWorld {
+        let levels: Vec<LevelDef> = ron::de::from_str(LEVELS).expect("Error on parsing test levels.");
+        GameWorld::new(seed, levels.clone(), levels, test_archetypes(), 1)
+    }
+
+    /// Goes from the start menu through the how to play screen into the first level.
+    pub fn start(world: &mut GameWorld) {
+        let confirm = InputState { confirm: true, ..InputState::default() };
+        world.update(&confirm, 0.016);
+        world.update(&confirm, 0.016);
+        assert!(world.game_state == GameState::Running);
+    }
+
+    pub fn walk(move_x: f32, move_y: f32) -> InputState {
+        let mut input = InputState::default();
+        input.players[0] = PlayerInput { move_x, move_y, ..PlayerInput::default() };
+        input
+    }
+
+    #[test]
+    fn dog_walks() {
+        let mut world = test_world(1);
+        start(&mut world);
+        let start_pos = world.dogs[0].pos;
+        for _ in 0..10 {
+            world.update(&walk(1.0, 0.0), 0.1);
+        }
+        assert!((world.dogs[0].pos.x - start_pos.x - MOVE_SPEED).abs() < 0.01);
+        assert_eq!(world.dogs[0].pos.y, start_pos.y);
+    }
+
+    #[test]
+    fn joining_doesnt_bark() {
+        let mut world = test_world(7);
+        start(&mut world);
+        let mut join = InputState::default();
+        join.players[1].woof = true;
+        world.update(&join, 0.1);
+        assert_eq!(world.dogs.len(), 2);
+        assert!(!world.sound_events.contains(&SoundEvent::Woof(1)));
+        assert_eq!(world.dogs[1].woof_cooldown, 0.0);
+
+        world.update(&join, 0.1);
+        assert!(world.sound_events.contains(&SoundEvent::Woof(1)));
+    }
+
+    #[test]
+    fn only_the_keyboard_quits_from_the_title_screen() {
+        let mut world = test_world(8);
+        // Start on a gamepad.
+        let mut pads = GamepadSource::default();
+        pads.handle(0, PadEvent::Pressed(Button::Start));
+        world.update(&InputState::poll(&[&pads]), 0.1);
+        assert!(world.app_request.is_none());
+        assert!(world.game_state == GameState::StartMenu);
+
+        world.update(&InputState { quit: true, ..InputState::default() }, 0.1);
+        assert!(world.app_request == Some(AppRequest::Quit));
+    }
+
+    #[test]
+    fn penning_every_cat_wins() {
+        let mut world = test_world(2);
+        start(&mut world);
+        world.update(&InputState::default(), 0.1);
+        assert!(world.game_state == GameState::Running);
+
+        let box_pos = world.level.cat_boxes[0].pos;
+        for cat in &mut world.cats {
+            cat.pos = box_pos;
+        }
+        world.update(&InputState::default(), 0.1);
+        assert!(world.game_state == GameState::Won);
+        assert!(world.result.is_some());
+
+        world.update(&InputState { next_level: true, ..InputState::default() }, 0.1);
+        assert!(world.game_state == GameState::Running);
+        assert_eq!(world.level.level_num, 2);
+        assert_eq!(world.cats.len(), 3);
+    }
+
+    #[test]
+    fn only_skip_level_skips() {
+        let mut world = test_world(5);
+        start(&mut world);
+        world.update(&InputState { back: true, ..InputState::default() }, 0.1);
+        assert!(world.game_state == GameState::Running);
+        assert_eq!(world.level.level_num, 1);
+
+        world.update(&InputState { skip_level: true, ..InputState::default() }, 0.1);
+        assert_eq!(world.level.level_num, 2);
+    }
+
+    #[test]
+    fn penned_cats_wander_inside_the_box() {
+        let mut world = test_world(6);
+        start(&mut world);
+        let cat_box = world.level.cat_boxes[0].pos;
+        // Just inside the edge, where a cat that ran in stops.
+        world.cats[0].pos = cat_box + cgmath::vec2(29.0, 0.0);
+        let mut turns = 0;
+        let mut facing = world.cats[0].facing;
+        for _ in 0..120 {
+            world.update(&InputState::default(), 0.016);
+            assert!(world.cats[0].state == CatState::InPen);
+            if world.cats[0].facing != facing {
+                turns += 1;
+                facing = world.cats[0].facing;
+            }
+        }
+        assert!(world.cats[0].pos.x < cat_box.x + 26.0);
+        assert!(turns < 10);
+    }
+
+    #[test]
+    fn barking_at_the_pen_sets_off_escapes() {
+        let mut world = test_world(10);
+        start(&mut world);
+        let cat_box = world.level.cat_boxes[0].pos;
+        world.cats[0].pos = cat_box;
+        world.cats[0].escape_chance = 1.0;
+        world.dogs[0].pos = cat_box + cgmath::vec2(40.0, 0.0);
+        let mut bark = InputState::default();
+        bark.players[0].woof = true;
+        let mut escaped = false;
+        for _ in 0..20 {
+            world.update(&bark, 0.016);
+            assert!(world.cats[0].impulse.is_zero());
+            for _ in 0..100 {
+                world.update(&InputState::default(), 0.016);
+                escaped |= world.cats[0].state == CatState::Escaping;
+            }
+            if escaped {
+                break;
             }
         }
+        assert!(escaped);
     }
 
-    pub fn cat_box(&self) -> &CatBox {
-        &self.level.cat_box
+    #[test]
+    fn level_time_is_real_time() {
+        let mut world = test_world(9);
+        start(&mut world);
+        world.game_speed = 0.5;
+        // A second of real time at half speed.
+        for _ in 0..10 {
+            world.update(&InputState::default(), 0.05);
+        }
+        assert!((world.level_time - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn running_out_of_health_fails() {
+        let mut world = test_world(3);
+        start(&mut world);
+        for _ in 0..DOG_HEALTH {
+            world.dogs[0].hit(cgmath::vec2(1.0, 0.0));
+        }
+        world.update(&InputState::default(), 0.1);
+        assert!(world.game_state == GameState::Failed);
+
+        world.update(&InputState { restart: true, ..InputState::default() }, 0.1);
+        assert!(world.game_state == GameState::Running);
+        assert_eq!(world.dogs[0].health, DOG_HEALTH);
+    }
+
+    #[test]
+    fn pausing_freezes_the_level() {
+        let mut world = test_world(4);
+        start(&mut world);
+        let pause = InputState { pause: true, ..InputState::default() };
+        world.update(&pause, 0.1);
+        assert!(world.game_state == GameState::Paused);
+
+        let dog_pos = world.dogs[0].pos;
+        let cat_pos: Vec<_> = world.cats.iter().map(|cat| cat.pos).collect();
+        let level_time = world.level_time;
+        for _ in 0..10 {
+            world.update(&walk(1.0, 1.0), 0.1);
+        }
+        assert_eq!(world.dogs[0].pos, dog_pos);
+        assert_eq!(world.cats.iter().map(|cat| cat.pos).collect::<Vec<_>>(), cat_pos);
+        assert_eq!(world.level_time, level_time);
+
+        world.update(&pause, 0.1);
+        assert!(world.game_state == GameState::Running);
+        world.update(&walk(1.0, 0.0), 0.1);
+        assert!(world.dogs[0].pos.x > dog_pos.x);
     }
 }